@@ -0,0 +1,375 @@
+use druid::widget::{Button, Checkbox, Controller, CrossAxisAlignment, Flex, Label, RadioGroup, Slider, TextBox};
+use druid::text::ParseFormatter;
+use druid::{commands, lens, Color, Data, Env, Event, EventCtx, FileDialogOptions, FileSpec, Selector, Widget, WidgetExt, WindowDesc};
+use crate::utils::{Adjustments, AppState, Border, CanvasColor, Frame, FrameBackground, ImageSize, Margins, ResizeFilter};
+use crate::watermark::{Watermark, WatermarkPosition};
+use crate::mockups::{DeviceFrame, Mockup};
+
+/// Asks the drawing widget to open the Image Size dialog, as menus cannot open windows themselves.
+pub const SHOW_IMAGE_SIZE: Selector = Selector::new("screen-crab-tools.show-image-size");
+pub const SHOW_CANVAS_SIZE: Selector = Selector::new("screen-crab-tools.show-canvas-size");
+pub const SHOW_BORDER: Selector = Selector::new("screen-crab-tools.show-border");
+pub const SHOW_FRAME: Selector = Selector::new("screen-crab-tools.show-frame");
+pub const SHOW_ADJUSTMENTS: Selector = Selector::new("screen-crab-tools.show-adjustments");
+pub const SHOW_WATERMARK: Selector = Selector::new("screen-crab-tools.show-watermark");
+pub const SHOW_DEVICE_FRAME: Selector = Selector::new("screen-crab-tools.show-device-frame");
+
+/// Resets the Image Size dialog to the current size of the image and builds its window.
+pub fn image_size_window(data: &mut AppState) -> WindowDesc<AppState> {
+    let size = &mut data.image_size;
+    size.original_width = data.image.width() as f64;
+    size.original_height = data.image.height() as f64;
+    size.width = size.original_width;
+    size.height = size.original_height;
+    WindowDesc::new(image_size_dialog())
+        .title("Image Size")
+        .window_size((320.0, 340.0))
+        .resizable(false)
+}
+
+/// Builds the window of the Canvas Size dialog, which keeps the margins last added.
+pub fn canvas_size_window() -> WindowDesc<AppState> {
+    WindowDesc::new(canvas_size_dialog())
+        .title("Canvas Size")
+        .window_size((320.0, 360.0))
+        .resizable(false)
+}
+
+/// Builds the window of the Border dialog, which keeps the border last added.
+pub fn border_window() -> WindowDesc<AppState> {
+    WindowDesc::new(border_dialog())
+        .title("Border")
+        .window_size((320.0, 300.0))
+        .resizable(false)
+}
+
+/// Builds the window of the Frame dialog, which keeps the frame last added.
+pub fn frame_window() -> WindowDesc<AppState> {
+    WindowDesc::new(frame_dialog())
+        .title("Frame")
+        .window_size((360.0, 460.0))
+        .resizable(false)
+}
+
+/// Builds the window of the Adjustments panel, whose changes show on the image as they are made.
+pub fn adjustments_window() -> WindowDesc<AppState> {
+    WindowDesc::new(adjustments_dialog())
+        .title("Adjustments")
+        .window_size((380.0, 340.0))
+        .resizable(false)
+}
+
+/// Builds the window of the Watermark dialog, whose changes show on the image as they are made.
+pub fn watermark_window() -> WindowDesc<AppState> {
+    WindowDesc::new(watermark_dialog())
+        .title("Watermark")
+        .window_size((420.0, 620.0))
+        .resizable(false)
+}
+
+/// Builds the window of the Device Frame dialog, which keeps the frame last chosen.
+pub fn device_frame_window() -> WindowDesc<AppState> {
+    WindowDesc::new(device_frame_dialog())
+        .title("Device Frame")
+        .window_size((360.0, 280.0))
+        .resizable(false)
+}
+
+/// A labelled text box for a number of pixels.
+fn pixels_field<T: druid::Data>(label: &str, field: impl druid::Lens<T, f64> + 'static) -> impl Widget<T> {
+    Flex::row()
+        .with_child(Label::new(label).fix_width(100.0))
+        .with_child(TextBox::new().with_formatter(ParseFormatter::new()).update_data_while_editing(true).lens(field).fix_width(80.0))
+        .with_child(Label::new("px"))
+}
+
+/// The Cancel button, and one applying the dialog before closing it.
+fn dialog_buttons(title: &str, apply: impl Fn(&mut AppState) + 'static) -> impl Widget<AppState> {
+    Flex::row()
+        .with_child(Button::new("Cancel").on_click(|ctx, _: &mut AppState, _| {
+            ctx.submit_command(commands::CLOSE_WINDOW.to(ctx.window_id()));
+        }))
+        .with_spacer(8.0)
+        .with_child(Button::new(title).on_click(move |ctx, data: &mut AppState, _| {
+            apply(data);
+            ctx.submit_command(commands::CLOSE_WINDOW.to(ctx.window_id()));
+        }))
+}
+
+/// The dialog around `settings`, with its buttons at the bottom.
+fn dialog(settings: impl Widget<AppState> + 'static, buttons: impl Widget<AppState> + 'static) -> impl Widget<AppState> {
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(settings)
+        .with_flex_spacer(1.0)
+        .with_child(buttons)
+        .padding(16.0)
+        .expand()
+        .background(Color::WHITE)
+}
+
+fn canvas_size_dialog() -> impl Widget<AppState> {
+    let settings = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(pixels_field("Top:", Margins::top))
+        .with_spacer(8.0)
+        .with_child(pixels_field("Right:", Margins::right))
+        .with_spacer(8.0)
+        .with_child(pixels_field("Bottom:", Margins::bottom))
+        .with_spacer(8.0)
+        .with_child(pixels_field("Left:", Margins::left))
+        .with_spacer(8.0)
+        .with_child(Label::new("Background:"))
+        .with_child(RadioGroup::column(vec![
+            ("White", CanvasColor::White),
+            ("Black", CanvasColor::Black),
+            ("Drawing Color", CanvasColor::Drawing),
+            ("Transparent", CanvasColor::Transparent),
+        ]).lens(Margins::background))
+        .lens(AppState::margins);
+    dialog(settings, dialog_buttons("Add Margins", AppState::expand_canvas))
+}
+
+fn border_dialog() -> impl Widget<AppState> {
+    let settings = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(pixels_field("Width:", Border::width))
+        .with_spacer(8.0)
+        .with_child(pixels_field("Corner Radius:", Border::radius))
+        .with_spacer(8.0)
+        .with_child(Label::new("Color:"))
+        .with_child(RadioGroup::column(vec![
+            ("Black", CanvasColor::Black),
+            ("White", CanvasColor::White),
+            ("Gray", CanvasColor::Gray),
+            ("Drawing Color", CanvasColor::Drawing),
+        ]).lens(Border::color))
+        .lens(AppState::border);
+    dialog(settings, dialog_buttons("Add Border", AppState::add_border))
+}
+
+/// A side of the image in the unit shown, percents being rounded to two decimals.
+fn shown(percent: bool, pixels: f64, original: f64) -> f64 {
+    if percent { (pixels / original * 10000f64).round() / 100f64 } else { pixels }
+}
+
+fn image_size_dialog() -> impl Widget<AppState> {
+    // the lenses only write back what was typed: they are also run for events that change nothing
+    let width = TextBox::new().with_formatter(ParseFormatter::new()).update_data_while_editing(true)
+        .lens(lens::Map::new(
+            |size: &ImageSize| shown(size.percent, size.width, size.original_width),
+            |size: &mut ImageSize, width: f64| {
+                if width != shown(size.percent, size.width, size.original_width) {
+                    size.set_width(if size.percent { size.original_width * width / 100f64 } else { width });
+                }
+            },
+        ));
+    let height = TextBox::new().with_formatter(ParseFormatter::new()).update_data_while_editing(true)
+        .lens(lens::Map::new(
+            |size: &ImageSize| shown(size.percent, size.height, size.original_height),
+            |size: &mut ImageSize, height: f64| {
+                if height != shown(size.percent, size.height, size.original_height) {
+                    size.set_height(if size.percent { size.original_height * height / 100f64 } else { height });
+                }
+            },
+        ));
+    let settings = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Flex::row().with_child(Label::new("Width:").fix_width(60.0)).with_child(width.fix_width(100.0)))
+        .with_spacer(8.0)
+        .with_child(Flex::row().with_child(Label::new("Height:").fix_width(60.0)).with_child(height.fix_width(100.0)))
+        .with_spacer(8.0)
+        .with_child(RadioGroup::row(vec![("Pixels", false), ("Percent", true)]).lens(ImageSize::percent))
+        .with_child(Checkbox::new("Keep aspect ratio").lens(ImageSize::locked))
+        .with_spacer(8.0)
+        .with_child(Label::new("Resampling:"))
+        .with_child(RadioGroup::column(vec![
+            ("Nearest Neighbor", ResizeFilter::Nearest),
+            ("Bilinear", ResizeFilter::Triangle),
+            ("Bicubic", ResizeFilter::CatmullRom),
+            ("Lanczos", ResizeFilter::Lanczos3),
+        ]).lens(ImageSize::filter))
+        .lens(AppState::image_size);
+    dialog(settings, dialog_buttons("Resize", AppState::resize_image))
+}
+
+/// Takes the image picked in the open panel of a dialog, handing its path to the function it holds.
+struct PickImage<T>(fn(&mut T, String));
+
+impl<T: Data, W: Widget<T>> Controller<T, W> for PickImage<T> {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut T, env: &Env) {
+        if let Event::Command(command) = event {
+            if let Some(file) = command.get(commands::OPEN_FILE) {
+                (self.0)(data, file.path().to_string_lossy().to_string());
+                ctx.set_handled();
+                return;
+            }
+        }
+        child.event(ctx, event, data, env)
+    }
+}
+
+/// A button opening a panel to pick an image of one of the given types, taken by a `PickImage` controller.
+fn choose_image<T: Data>(types: Vec<FileSpec>) -> impl Widget<T> {
+    Button::new("Choose Image...").on_click(move |ctx, _: &mut T, _| {
+        let options = FileDialogOptions::new().allowed_types(types.clone());
+        ctx.submit_command(commands::SHOW_OPEN_PANEL.with(options).to(ctx.window_id()));
+    })
+}
+
+fn frame_dialog() -> impl Widget<AppState> {
+    let settings = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(pixels_field("Padding:", Frame::padding))
+        .with_spacer(8.0)
+        .with_child(pixels_field("Corner Radius:", Frame::radius))
+        .with_spacer(8.0)
+        .with_child(pixels_field("Shadow:", Frame::shadow))
+        .with_spacer(8.0)
+        .with_child(Label::new("Background:"))
+        .with_child(RadioGroup::column(vec![
+            ("Sunset", FrameBackground::Sunset),
+            ("Ocean", FrameBackground::Ocean),
+            ("Lavender", FrameBackground::Lavender),
+            ("Graphite", FrameBackground::Graphite),
+            ("Drawing Color", FrameBackground::Color),
+            ("Image", FrameBackground::Image),
+        ]).lens(Frame::background))
+        .with_spacer(8.0)
+        .with_child(Flex::row()
+            .with_child(TextBox::new().with_placeholder("Image file").lens(Frame::image_path).fix_width(200.0))
+            .with_spacer(8.0)
+            .with_child(choose_image(vec![FileSpec::PNG, FileSpec::JPG])))
+        .controller(PickImage(|frame: &mut Frame, path| {
+            frame.image_path = path;
+            frame.background = FrameBackground::Image;
+        }))
+        .lens(AppState::frame);
+    dialog(settings, dialog_buttons("Add Frame", AppState::add_frame))
+}
+
+/// A labelled slider over `min..max`, with its value.
+fn slider_field<T: Data>(label: &str, min: f64, max: f64, field: impl druid::Lens<T, f64> + Clone + 'static) -> impl Widget<T> {
+    Flex::row()
+        .with_child(Label::new(label).fix_width(90.0))
+        .with_child(Slider::new().with_range(min, max).lens(field.clone()).fix_width(180.0))
+        .with_spacer(8.0)
+        .with_child(Label::dynamic(|value: &f64, _| format!("{:.1}", value)).lens(field))
+}
+
+fn adjustments_dialog() -> impl Widget<AppState> {
+    let settings = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(slider_field("Brightness:", -100.0, 100.0, Adjustments::brightness))
+        .with_spacer(8.0)
+        .with_child(slider_field("Contrast:", -100.0, 100.0, Adjustments::contrast))
+        .with_spacer(8.0)
+        .with_child(slider_field("Gamma:", 0.2, 5.0, Adjustments::gamma))
+        .with_spacer(8.0)
+        .with_child(slider_field("Saturation:", -100.0, 100.0, Adjustments::saturation))
+        .with_spacer(8.0)
+        .with_child(slider_field("Sharpen:", 0.0, 100.0, Adjustments::sharpen))
+        .with_spacer(8.0)
+        .with_child(Checkbox::new("Grayscale").lens(Adjustments::grayscale))
+        .with_spacer(4.0)
+        .with_child(Checkbox::new("Invert").lens(Adjustments::invert))
+        .lens(AppState::adjustments);
+    // the adjustments are already shown, so there is nothing to apply
+    let buttons = Flex::row()
+        .with_child(Button::new("Reset").on_click(|_, data: &mut AppState, _| {
+            data.adjustments = Adjustments::default();
+        }))
+        .with_spacer(8.0)
+        .with_child(Button::new("Done").on_click(|ctx, _: &mut AppState, _| {
+            ctx.submit_command(commands::CLOSE_WINDOW.to(ctx.window_id()));
+        }));
+    dialog(settings, buttons)
+}
+
+fn watermark_dialog() -> impl Widget<AppState> {
+    // the image is read again whenever its path changes
+    let image_path = TextBox::new().with_placeholder("PNG or SVG file")
+        .lens(lens::Map::new(
+            |mark: &Watermark| mark.image_path.clone(),
+            |mark: &mut Watermark, path: String| {
+                if path != mark.image_path {
+                    mark.set_image_path(path);
+                }
+            },
+        ));
+    let settings = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Checkbox::new("Show watermark").lens(Watermark::enabled))
+        .with_spacer(8.0)
+        .with_child(RadioGroup::row(vec![("Text", false), ("Image", true)]).lens(Watermark::use_image))
+        .with_spacer(8.0)
+        .with_child(Flex::row()
+            .with_child(Label::new("Text:").fix_width(60.0))
+            .with_child(TextBox::new().lens(Watermark::text).fix_width(240.0)))
+        .with_spacer(8.0)
+        .with_child(Flex::row()
+            .with_child(Label::new("Image:").fix_width(60.0))
+            .with_child(image_path.fix_width(160.0))
+            .with_spacer(8.0)
+            .with_child(choose_image(vec![FileSpec::PNG, FileSpec::new("SVG", &["svg"])])))
+        .with_spacer(8.0)
+        .with_child(Label::new("Text Color:"))
+        .with_child(RadioGroup::row(vec![
+            ("Gray", CanvasColor::Gray),
+            ("White", CanvasColor::White),
+            ("Black", CanvasColor::Black),
+            ("Drawing Color", CanvasColor::Drawing),
+        ]).lens(Watermark::color))
+        .with_spacer(8.0)
+        .with_child(Label::new("Position:"))
+        .with_child(RadioGroup::column(vec![
+            ("Top Left", WatermarkPosition::TopLeft),
+            ("Top Right", WatermarkPosition::TopRight),
+            ("Bottom Left", WatermarkPosition::BottomLeft),
+            ("Bottom Right", WatermarkPosition::BottomRight),
+            ("Center", WatermarkPosition::Center),
+            ("Tiled", WatermarkPosition::Tiled),
+        ]).lens(Watermark::position))
+        .with_spacer(8.0)
+        .with_child(slider_field("Opacity:", 0.0, 1.0, Watermark::opacity))
+        .with_spacer(8.0)
+        .with_child(slider_field("Rotation:", -90.0, 90.0, Watermark::rotation))
+        .with_spacer(8.0)
+        .with_child(slider_field("Width (%):", 5.0, 100.0, Watermark::size))
+        .controller(PickImage(|mark: &mut Watermark, path| {
+            mark.set_image_path(path);
+            mark.use_image = true;
+        }))
+        // a watermark given on the command line is kept as it was given
+        .disabled_if(|mark: &Watermark, _| mark.enforced)
+        .lens(AppState::watermark);
+    // the watermark is already shown, so there is nothing to apply
+    let buttons = Flex::row()
+        .with_child(Button::new("Save as Default").on_click(|_, data: &mut AppState, _| {
+            data.watermark.save_defaults();
+        }).disabled_if(|data: &AppState, _| data.watermark.enforced))
+        .with_spacer(8.0)
+        .with_child(Button::new("Done").on_click(|ctx, _: &mut AppState, _| {
+            ctx.submit_command(commands::CLOSE_WINDOW.to(ctx.window_id()));
+        }));
+    dialog(settings, buttons)
+}
+
+fn device_frame_dialog() -> impl Widget<AppState> {
+    let settings = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new("Frame:"))
+        .with_child(RadioGroup::column(vec![
+            ("Phone", DeviceFrame::Phone),
+            ("Tablet", DeviceFrame::Tablet),
+            ("Browser Window", DeviceFrame::Browser),
+            ("Terminal Window", DeviceFrame::Terminal),
+        ]).lens(Mockup::frame))
+        .with_spacer(8.0)
+        .with_child(Flex::row()
+            .with_child(Label::new("Title / URL:").fix_width(90.0))
+            .with_child(TextBox::new().lens(Mockup::title).fix_width(220.0))
+            .disabled_if(|mockup: &Mockup, _| !matches!(mockup.frame, DeviceFrame::Browser | DeviceFrame::Terminal)))
+        .lens(AppState::mockup);
+    dialog(settings, dialog_buttons("Add Frame", AppState::add_device_frame))
+}
@@ -2,6 +2,10 @@
 mod utils;
 mod painter;
 mod menu;
+mod stamps;
+mod dialogs;
+mod watermark;
+mod mockups;
 
 use std::fs;
 use std::path::Path;
@@ -14,7 +18,7 @@ use crate::painter::DrawingWidget;
 
 
 fn ui_builder() -> impl Widget<AppState> {
-    let drawing = Flex::row().with_child(DrawingWidget).padding(0.0);
+    let drawing = Flex::row().with_child(DrawingWidget::new()).padding(0.0);
     Align::centered(Scroll::new(drawing))
 }
 
@@ -22,12 +26,12 @@ fn main() -> Result<(), PlatformError> {
     let arg = utils::Args::parse();
 
     //check if the file exists
-    if let Err(_) = fs::metadata(arg.path.to_string()) {
+    if fs::metadata(&arg.path).is_err() {
         utils::dialog_file_not_found(arg.path.to_string());
         exit(255);
     }
 
-    let extension = std::path::Path::new(arg.path.to_string().as_str()).extension().unwrap().to_os_string().into_string().unwrap().to_lowercase();
+    let extension = std::path::Path::new(arg.path.as_str()).extension().unwrap().to_os_string().into_string().unwrap().to_lowercase();
 
     if !extension.eq("png") && !extension.eq("jpeg") && !extension.eq("jpg") && !extension.eq("tiff") && !extension.eq("bmp") {
         utils::dialog_not_supported(arg.path.to_string());
@@ -35,7 +39,12 @@ fn main() -> Result<(), PlatformError> {
     }
 
     let monitor = Screen::get_monitors().first().unwrap().clone();
-    let image = image::io::Reader::open(arg.path.to_string()).unwrap().with_guessed_format().unwrap().decode().unwrap();
+    let mut image = image::io::Reader::open(&arg.path).unwrap().with_guessed_format().unwrap().decode().unwrap();
+    if arg.auto_trim {
+        if let Some((x, y, width, height)) = utils::trim_area(&image, arg.trim_tolerance) {
+            image = image.crop_imm(x, y, width, height);
+        }
+    }
 
     let monitor_width = monitor.virtual_work_rect().width();
     let monitor_height = monitor.virtual_rect().height();
@@ -46,7 +55,7 @@ fn main() -> Result<(), PlatformError> {
     #[cfg(target_os = "macos")] { title_bar_height = 3.3f64/100f64 * monitor_height; }
     #[cfg(target_os = "linux")] { title_bar_height = 8.5f64/100f64 * monitor_height; }
 
-    let initial_state = AppState::new(
+    let mut initial_state = AppState::new(
         image,
         title_bar_height,
         extension,
@@ -55,13 +64,20 @@ fn main() -> Result<(), PlatformError> {
         monitor,
         Color::RED
     );
+    initial_state.trim_tolerance = arg.trim_tolerance;
+    if let Some(mark) = &arg.watermark {
+        if !initial_state.watermark.enforce(mark) {
+            utils::dialog_watermark_not_read(mark.to_string());
+            exit(253);
+        }
+    }
 
         initial_state.scale_factor.set(image_width / monitor_width + 0.5f64);
 
     let main_window = WindowDesc::new(ui_builder())
         .title(format!("Screen Crab Tools - [{}]", Path::new(arg.path.to_string().as_str()).canonicalize().unwrap().to_str().unwrap()))
-        .menu(|_, _, _| {
-            menu::create_menu()
+        .menu(|_, data, _| {
+            menu::create_menu(data)
         });
 
     AppLauncher::with_window(main_window)
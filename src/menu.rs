@@ -2,11 +2,13 @@ use std::fs;
 use std::process::exit;
 #[cfg(target_os = "macos")]
 use druid::commands;
-use druid::{Affine, Color, Env};
-use crate::utils::{Action, AppState, Selection};
+use druid::{Affine, Color, Env, TextAlignment};
+use druid::piet::{LineCap, LineJoin};
+use crate::utils::{Action, AppState, ArrowHead, ArrowTail, DashStyle, EraserMode, HighlighterTip, Selection, StepStyle};
 use druid::RawMods;
+use crate::dialogs;
 
-pub fn create_menu() -> druid::Menu<AppState> {
+pub fn create_menu(data: &AppState) -> druid::Menu<AppState> {
 
     #[cfg(target_os = "macos")]
     let about = druid::Menu::new(druid::LocalizedString::new("Screen Crab Tools"))
@@ -28,11 +30,24 @@ pub fn create_menu() -> druid::Menu<AppState> {
         )
         .entry(druid::MenuItem::new("Delete").hotkey(Some(RawMods::Meta), "D")
             .on_activate(move |_, data: &mut AppState, _| {
-                    fs::remove_file(data.image_path.to_string()).unwrap();
+                    fs::remove_file(&data.image_path).unwrap();
                     exit(0);
             })
         );
 
+    let stamps = data.stamps.iter().enumerate().fold(druid::Menu::new(druid::LocalizedString::new("Stamps"))
+        .enabled_if(|data: &AppState, _| {
+            data.selection == Selection::Stamp
+        }), |menu, (index, stamp)| {
+            menu.entry(druid::MenuItem::new(stamp.name.as_str())
+                .on_activate(move |_, data: &mut AppState, _| {
+                    data.stamp = index;
+                })
+                .selected_if(move |data: &AppState, _| {
+                    data.stamp == index
+                })
+            )
+        });
 
     let tools = druid::Menu::new(druid::LocalizedString::new("Tools"))
         .entry(druid::MenuItem::new("Pen").hotkey(Some(RawMods::Meta), "P")
@@ -42,6 +57,35 @@ pub fn create_menu() -> druid::Menu<AppState> {
             .on_activate(|_, data: &mut AppState, _| {
                 data.selection = Selection::Pen;
             }))
+        .entry(druid::Menu::new(druid::LocalizedString::new("Pen Style"))
+            .enabled_if(|data: &AppState, _| {
+                data.selection == Selection::Pen
+            })
+            .entry(druid::MenuItem::new("Smooth")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.pen_smoothing = !data.pen_smoothing;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.pen_smoothing
+                })
+            )
+            .entry(druid::MenuItem::new("Vary Width with Speed")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.pen_velocity = !data.pen_velocity;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.pen_velocity
+                })
+            )
+            .entry(druid::MenuItem::new("Recognize Shapes")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.smart_pen = !data.smart_pen;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.smart_pen
+                })
+            )
+        )
         .entry(druid::MenuItem::new("Highlighter").hotkey(Some(RawMods::Meta), "H")
             .selected_if(|data: &AppState, _| {
                 data.selection.eq(&Selection::Highlighter)
@@ -49,14 +93,138 @@ pub fn create_menu() -> druid::Menu<AppState> {
             .on_activate(|_, data: &mut AppState, _| {
                 data.selection = Selection::Highlighter;
             }))
+        .entry(druid::Menu::new(druid::LocalizedString::new("Highlighter Style"))
+            .enabled_if(|data: &AppState, _| {
+                data.selection == Selection::Highlighter
+            })
+            .entry(druid::MenuItem::new("Round Tip")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.highlighter_tip = HighlighterTip::Round;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.highlighter_tip == HighlighterTip::Round
+                })
+            )
+            .entry(druid::MenuItem::new("Chisel Tip")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.highlighter_tip = HighlighterTip::Chisel;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.highlighter_tip == HighlighterTip::Chisel
+                })
+            )
+            .separator()
+            .entry(druid::MenuItem::new("Straight Line")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.straight_highlight = !data.straight_highlight;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.straight_highlight
+                })
+            )
+        )
         .entry(druid::Menu::new(druid::LocalizedString::new("Shapes"))
                    .entry(druid::MenuItem::new("Fill")
                        .selected_if(|data: &AppState, _| {
-                           data.fill_color == true
+                           data.fill_color
                        })
                        .on_activate(|_, data: &mut AppState, _| {
                             data.fill_color = !data.fill_color
                        }))
+                   .entry(druid::Menu::new(druid::LocalizedString::new("Fill Color"))
+                       .enabled_if(|data: &AppState, _| {
+                           data.fill_color
+                       })
+                       .entry(druid::MenuItem::new("Same as Stroke")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.fill = None;
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.fill.is_none()
+                           })
+                       )
+                       .entry(druid::MenuItem::new("Red")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.fill = Some(Color::RED);
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.fill == Some(Color::RED)
+                           })
+                       )
+                       .entry(druid::MenuItem::new("Green")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.fill = Some(Color::GREEN);
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.fill == Some(Color::GREEN)
+                           })
+                       )
+                       .entry(druid::MenuItem::new("Blue")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.fill = Some(Color::BLUE);
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.fill == Some(Color::BLUE)
+                           })
+                       )
+                       .entry(druid::MenuItem::new("Yellow")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.fill = Some(Color::YELLOW);
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.fill == Some(Color::YELLOW)
+                           })
+                       )
+                       .entry(druid::MenuItem::new("Black")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.fill = Some(Color::BLACK);
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.fill == Some(Color::BLACK)
+                           })
+                       )
+                       .entry(druid::MenuItem::new("White")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.fill = Some(Color::WHITE);
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.fill == Some(Color::WHITE)
+                           })
+                       )
+                       .separator()
+                       .entry(druid::MenuItem::new("25% Opacity")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.fill_opacity = 0.25;
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.fill_opacity == 0.25
+                           })
+                       )
+                       .entry(druid::MenuItem::new("50% Opacity")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.fill_opacity = 0.5;
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.fill_opacity == 0.5
+                           })
+                       )
+                       .entry(druid::MenuItem::new("75% Opacity")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.fill_opacity = 0.75;
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.fill_opacity == 0.75
+                           })
+                       )
+                       .entry(druid::MenuItem::new("100% Opacity")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.fill_opacity = 1.0;
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.fill_opacity == 1.0
+                           })
+                       )
+                   )
                    .separator()
                    .entry(druid::menu::MenuItem::new("Rectangle")
                        .selected_if(|data: &AppState, _| {
@@ -81,7 +249,117 @@ pub fn create_menu() -> druid::Menu<AppState> {
                        .on_activate(|_, data: &mut AppState, _| {
                            data.selection = Selection::Ellipse;
                        }
-                   ))  // TBI
+                   ))
+                   .entry(druid::menu::MenuItem::new("Rounded Rectangle")
+                       .selected_if(|data: &AppState, _| {
+                           data.selection.eq(&Selection::RoundedRectangle)
+                       })
+                       .on_activate(|_, data: &mut AppState, _| {
+                           data.selection = Selection::RoundedRectangle;
+                       }))
+                   .entry(druid::menu::MenuItem::new("Triangle")
+                       .selected_if(|data: &AppState, _| {
+                           data.selection.eq(&Selection::Triangle)
+                       })
+                       .on_activate(|_, data: &mut AppState, _| {
+                           data.selection = Selection::Triangle;
+                       }))
+                   .entry(druid::menu::MenuItem::new("Diamond")
+                       .selected_if(|data: &AppState, _| {
+                           data.selection.eq(&Selection::Diamond)
+                       })
+                       .on_activate(|_, data: &mut AppState, _| {
+                           data.selection = Selection::Diamond;
+                       }))
+                   .entry(druid::menu::MenuItem::new("Star")
+                       .selected_if(|data: &AppState, _| {
+                           data.selection.eq(&Selection::Star)
+                       })
+                       .on_activate(|_, data: &mut AppState, _| {
+                           data.selection = Selection::Star;
+                       }))
+                   .entry(druid::menu::MenuItem::new("Polygon")
+                       .selected_if(|data: &AppState, _| {
+                           data.selection.eq(&Selection::Polygon)
+                       })
+                       .on_activate(|_, data: &mut AppState, _| {
+                           data.selection = Selection::Polygon;
+                       }))
+                   .separator()
+                   .entry(druid::Menu::new(druid::LocalizedString::new("Corner Radius"))
+                       .enabled_if(|data: &AppState, _| {
+                           data.selection == Selection::RoundedRectangle
+                       })
+                       .entry(druid::MenuItem::new("4 pt")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.corner_radius = 4.0;
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.corner_radius == 4.0
+                           })
+                       )
+                       .entry(druid::MenuItem::new("8 pt")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.corner_radius = 8.0;
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.corner_radius == 8.0
+                           })
+                       )
+                       .entry(druid::MenuItem::new("16 pt")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.corner_radius = 16.0;
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.corner_radius == 16.0
+                           })
+                       )
+                       .entry(druid::MenuItem::new("24 pt")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.corner_radius = 24.0;
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.corner_radius == 24.0
+                           })
+                       )
+                   )
+                   .entry(druid::Menu::new(druid::LocalizedString::new("Polygon Sides"))
+                       .enabled_if(|data: &AppState, _| {
+                           data.selection == Selection::Polygon
+                       })
+                       .entry(druid::MenuItem::new("5")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.polygon_sides = 5;
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.polygon_sides == 5
+                           })
+                       )
+                       .entry(druid::MenuItem::new("6")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.polygon_sides = 6;
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.polygon_sides == 6
+                           })
+                       )
+                       .entry(druid::MenuItem::new("8")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.polygon_sides = 8;
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.polygon_sides == 8
+                           })
+                       )
+                       .entry(druid::MenuItem::new("10")
+                           .on_activate(|_, data: &mut AppState, _| {
+                               data.polygon_sides = 10;
+                           })
+                           .selected_if(|data: &AppState, _| {
+                               data.polygon_sides == 10
+                           })
+                       )
+                   )
         )
         .entry( druid::MenuItem::new("Arrow").hotkey(Some(RawMods::Meta), "W")
             .on_activate(|_, data: &mut AppState, _| {
@@ -90,6 +368,90 @@ pub fn create_menu() -> druid::Menu<AppState> {
             .selected_if(|data: &AppState, _| {
                 data.selection.eq(&Selection::Arrow)
             }))
+        .entry(druid::Menu::new(druid::LocalizedString::new("Arrow Style"))
+            .enabled_if(|data: &AppState, _| {
+                data.selection == Selection::Arrow
+            })
+            .entry(druid::MenuItem::new("Open Head")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.arrow_style.head = ArrowHead::Open;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.arrow_style.head == ArrowHead::Open
+                })
+            )
+            .entry(druid::MenuItem::new("Filled Head")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.arrow_style.head = ArrowHead::Filled;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.arrow_style.head == ArrowHead::Filled
+                })
+            )
+            .separator()
+            .entry(druid::MenuItem::new("No Tail")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.arrow_style.tail = ArrowTail::None;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.arrow_style.tail == ArrowTail::None
+                })
+            )
+            .entry(druid::MenuItem::new("Double-Headed")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.arrow_style.tail = ArrowTail::Head;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.arrow_style.tail == ArrowTail::Head
+                })
+            )
+            .entry(druid::MenuItem::new("Dot Tail")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.arrow_style.tail = ArrowTail::Dot;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.arrow_style.tail == ArrowTail::Dot
+                })
+            )
+            .entry(druid::MenuItem::new("Bar Tail")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.arrow_style.tail = ArrowTail::Bar;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.arrow_style.tail == ArrowTail::Bar
+                })
+            )
+            .separator()
+            .entry(druid::MenuItem::new("Curved")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.arrow_style.curved = !data.arrow_style.curved;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.arrow_style.curved
+                })
+            )
+        )
+        .entry(druid::MenuItem::new("Line").hotkey(Some(RawMods::Meta), "L")
+            .selected_if(|data: &AppState, _| {
+                data.selection.eq(&Selection::Line)
+            })
+            .on_activate(|_, data: &mut AppState, _| {
+                data.selection = Selection::Line;
+            }))
+        .entry(druid::MenuItem::new("Polyline").hotkey(Some(RawMods::Meta), "G")
+            .selected_if(|data: &AppState, _| {
+                data.selection.eq(&Selection::Polyline)
+            })
+            .on_activate(|_, data: &mut AppState, _| {
+                data.selection = Selection::Polyline;
+            }))
+        .entry(druid::MenuItem::new("Close Polyline")
+            .selected_if(|data: &AppState, _| {
+                data.close_polyline
+            })
+            .on_activate(|_, data: &mut AppState, _| {
+                data.close_polyline = !data.close_polyline;
+            }))
         .entry(druid::MenuItem::new("Text").hotkey(Some(RawMods::Meta), "T")
             .selected_if(|data: &AppState, _| {
                 data.selection.eq(&Selection::Text)
@@ -97,6 +459,225 @@ pub fn create_menu() -> druid::Menu<AppState> {
             .on_activate(|_, data: &mut AppState, _| {
                 data.selection = Selection::Text;
             }))
+        .entry(druid::MenuItem::new("Callout").hotkey(Some(RawMods::Meta), "B")
+            .selected_if(|data: &AppState, _| {
+                data.selection.eq(&Selection::Callout)
+            })
+            .on_activate(|_, data: &mut AppState, _| {
+                data.selection = Selection::Callout;
+            }))
+        .entry(druid::MenuItem::new("Step").hotkey(Some(RawMods::Meta), "N")
+            .selected_if(|data: &AppState, _| {
+                data.selection.eq(&Selection::Step)
+            })
+            .on_activate(|_, data: &mut AppState, _| {
+                data.selection = Selection::Step;
+            }))
+        .entry(druid::MenuItem::new("Magnifier").hotkey(Some(RawMods::Meta), "J")
+            .selected_if(|data: &AppState, _| {
+                data.selection.eq(&Selection::Magnifier)
+            })
+            .on_activate(|_, data: &mut AppState, _| {
+                data.selection = Selection::Magnifier;
+            }))
+        .entry(druid::Menu::new(druid::LocalizedString::new("Magnifier Style"))
+            .enabled_if(|data: &AppState, _| {
+                data.selection == Selection::Magnifier
+            })
+            .entry(druid::MenuItem::new("Rectangular")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.magnifier_style.round = false;
+                })
+                .selected_if(|data: &AppState, _| {
+                    !data.magnifier_style.round
+                })
+            )
+            .entry(druid::MenuItem::new("Round")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.magnifier_style.round = true;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.magnifier_style.round
+                })
+            )
+            .separator()
+            .entry(druid::MenuItem::new("Connector Line")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.magnifier_style.connector = !data.magnifier_style.connector;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.magnifier_style.connector
+                })
+            )
+            .separator()
+            .entry(druid::MenuItem::new("2x")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.magnifier_style.zoom = 2.0;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.magnifier_style.zoom == 2.0
+                })
+            )
+            .entry(druid::MenuItem::new("3x")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.magnifier_style.zoom = 3.0;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.magnifier_style.zoom == 3.0
+                })
+            )
+            .entry(druid::MenuItem::new("4x")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.magnifier_style.zoom = 4.0;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.magnifier_style.zoom == 4.0
+                })
+            )
+        )
+        .entry(druid::MenuItem::new("Spotlight").hotkey(Some(RawMods::Meta), "I")
+            .selected_if(|data: &AppState, _| {
+                data.selection.eq(&Selection::Spotlight)
+            })
+            .on_activate(|_, data: &mut AppState, _| {
+                data.selection = Selection::Spotlight;
+            }))
+        .entry(druid::Menu::new(druid::LocalizedString::new("Spotlight Style"))
+            .enabled_if(|data: &AppState, _| {
+                data.selection == Selection::Spotlight
+            })
+            .entry(druid::MenuItem::new("Rectangular")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.spotlight_round = false;
+                })
+                .selected_if(|data: &AppState, _| {
+                    !data.spotlight_round
+                })
+            )
+            .entry(druid::MenuItem::new("Round")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.spotlight_round = true;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.spotlight_round
+                })
+            )
+            .separator()
+            .entry(druid::MenuItem::new("Dim Lightly")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.spotlight_dim = 0.3;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.spotlight_dim == 0.3
+                })
+            )
+            .entry(druid::MenuItem::new("Dim")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.spotlight_dim = 0.5;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.spotlight_dim == 0.5
+                })
+            )
+            .entry(druid::MenuItem::new("Dim Strongly")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.spotlight_dim = 0.75;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.spotlight_dim == 0.75
+                })
+            )
+            .separator()
+            .entry(druid::MenuItem::new("No Blur")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.spotlight_blur = 0.0;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.spotlight_blur == 0.0
+                })
+            )
+            .entry(druid::MenuItem::new("Blur")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.spotlight_blur = 8.0;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.spotlight_blur == 8.0
+                })
+            )
+            .entry(druid::MenuItem::new("Blur Strongly")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.spotlight_blur = 24.0;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.spotlight_blur == 24.0
+                })
+            )
+        )
+        .entry(druid::MenuItem::new("Stamp").hotkey(Some(RawMods::Meta), "U")
+            .selected_if(|data: &AppState, _| {
+                data.selection.eq(&Selection::Stamp)
+            })
+            .on_activate(|_, data: &mut AppState, _| {
+                data.selection = Selection::Stamp;
+            }))
+        .entry(stamps)
+        .entry(druid::Menu::new(druid::LocalizedString::new("Stamp Size"))
+            .enabled_if(|data: &AppState, _| {
+                data.selection == Selection::Stamp
+            })
+            .entry(druid::MenuItem::new("Small")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.stamp_size = 32.0;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.stamp_size == 32.0
+                })
+            )
+            .entry(druid::MenuItem::new("Medium")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.stamp_size = 64.0;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.stamp_size == 64.0
+                })
+            )
+            .entry(druid::MenuItem::new("Large")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.stamp_size = 128.0;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.stamp_size == 128.0
+                })
+            )
+        )
+        .entry(druid::MenuItem::new("Eraser").hotkey(Some(RawMods::Meta), "E")
+            .selected_if(|data: &AppState, _| {
+                data.selection.eq(&Selection::Eraser)
+            })
+            .on_activate(|_, data: &mut AppState, _| {
+                data.selection = Selection::Eraser;
+            }))
+        .entry(druid::Menu::new(druid::LocalizedString::new("Eraser Mode"))
+            .enabled_if(|data: &AppState, _| {
+                data.selection == Selection::Eraser
+            })
+            .entry(druid::MenuItem::new("Whole Annotations")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.eraser_mode = EraserMode::Object;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.eraser_mode == EraserMode::Object
+                })
+            )
+            .entry(druid::MenuItem::new("Strokes")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.eraser_mode = EraserMode::Stroke;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.eraser_mode == EraserMode::Stroke
+                })
+            )
+        )
         .separator()
         .entry(druid::Menu::new(druid::LocalizedString::new("Color"))
             .entry(druid::MenuItem::new("Pick a color...")
@@ -110,7 +691,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
                     false
                 })
                 .selected_if(|data: &AppState, _| {
-                    data.custom_color == true
+                    data.custom_color
                 })
                 .on_activate(|_, data: &mut AppState, _| {
                     data.color = Color::RED;
@@ -121,7 +702,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
                     data.color.eq(&Color::RED)
                 })
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.color = Color::RED;
+                    data.set_color(Color::RED);
                     data.custom_color = false;
                 }))
             .entry(druid::MenuItem::new("Green")
@@ -129,7 +710,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
                     data.color.eq(&Color::GREEN)
                 })
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.color = Color::GREEN;
+                    data.set_color(Color::GREEN);
                     data.custom_color = false;
                 }))
             .entry(druid::MenuItem::new("Black")
@@ -137,7 +718,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
                     data.color.eq(&Color::BLACK)
                 })
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.color = Color::BLACK;
+                    data.set_color(Color::BLACK);
                     data.custom_color = false;
                 }))
             .entry(druid::MenuItem::new("White")
@@ -145,7 +726,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
                     data.color.eq(&Color::WHITE)
                 })
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.color = Color::WHITE;
+                    data.set_color(Color::WHITE);
                     data.custom_color = false;
                 }))
             .entry(druid::MenuItem::new("Aqua")
@@ -153,7 +734,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
                     data.color.eq(&Color::AQUA)
                 })
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.color = Color::AQUA;
+                    data.set_color(Color::AQUA);
                     data.custom_color = false;
                 }))
             .entry(druid::MenuItem::new("Blue")
@@ -161,7 +742,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
                     data.color.eq(&Color::BLUE)
                 })
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.color = Color::BLUE;
+                    data.set_color(Color::BLUE);
                     data.custom_color = false;
                 }))
             .entry(druid::MenuItem::new("Fuchsia")
@@ -169,7 +750,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
                     data.color.eq(&Color::FUCHSIA)
                 })
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.color = Color::FUCHSIA;
+                    data.set_color(Color::FUCHSIA);
                     data.custom_color = false;
                 }))
             .entry(druid::MenuItem::new("Gray")
@@ -177,7 +758,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
                     data.color.eq(&Color::GRAY)
                 })
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.color = Color::GRAY;
+                    data.set_color(Color::GRAY);
                     data.custom_color = false;
                 }))
             .entry(druid::MenuItem::new("Maroon")
@@ -185,7 +766,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
                     data.color.eq(&Color::MAROON)
                 })
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.color = Color::MAROON;
+                    data.set_color(Color::MAROON);
                     data.custom_color = false;
                 }))
             .entry(druid::MenuItem::new("Yellow")
@@ -193,7 +774,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
                     data.color.eq(&Color::YELLOW)
                 })
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.color = Color::YELLOW;
+                    data.set_color(Color::YELLOW);
                     data.custom_color = false;
                 }))
             .entry(druid::MenuItem::new("Silver")
@@ -201,7 +782,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
                     data.color.eq(&Color::SILVER)
                 })
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.color = Color::SILVER;
+                    data.set_color(Color::SILVER);
                     data.custom_color = false;
                 }))
         )
@@ -231,13 +812,100 @@ pub fn create_menu() -> druid::Menu<AppState> {
                 })
             )
         )
+        .entry(druid::Menu::new(druid::LocalizedString::new("Line Style"))
+            .enabled_if(|data: &AppState, _| {
+                !matches!(data.selection, Selection::Highlighter | Selection::Text | Selection::Callout | Selection::Step | Selection::Magnifier | Selection::Spotlight | Selection::Stamp | Selection::Crop)
+            })
+            .entry(druid::MenuItem::new("Solid")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.line_style.dash = DashStyle::Solid;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.line_style.dash == DashStyle::Solid
+                })
+            )
+            .entry(druid::MenuItem::new("Dashed")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.line_style.dash = DashStyle::Dashed;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.line_style.dash == DashStyle::Dashed
+                })
+            )
+            .entry(druid::MenuItem::new("Dotted")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.line_style.dash = DashStyle::Dotted;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.line_style.dash == DashStyle::Dotted
+                })
+            )
+            .entry(druid::MenuItem::new("Dash-Dot")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.line_style.dash = DashStyle::DashDot;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.line_style.dash == DashStyle::DashDot
+                })
+            )
+            .separator()
+            .entry(druid::MenuItem::new("Butt Cap")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.line_style.cap = LineCap::Butt;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.line_style.cap == LineCap::Butt
+                })
+            )
+            .entry(druid::MenuItem::new("Round Cap")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.line_style.cap = LineCap::Round;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.line_style.cap == LineCap::Round
+                })
+            )
+            .entry(druid::MenuItem::new("Square Cap")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.line_style.cap = LineCap::Square;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.line_style.cap == LineCap::Square
+                })
+            )
+            .separator()
+            .entry(druid::MenuItem::new("Miter Join")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.line_style.join = LineJoin::Miter { limit: LineJoin::DEFAULT_MITER_LIMIT };
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.line_style.join == LineJoin::Miter { limit: LineJoin::DEFAULT_MITER_LIMIT }
+                })
+            )
+            .entry(druid::MenuItem::new("Round Join")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.line_style.join = LineJoin::Round;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.line_style.join == LineJoin::Round
+                })
+            )
+            .entry(druid::MenuItem::new("Bevel Join")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.line_style.join = LineJoin::Bevel;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.line_style.join == LineJoin::Bevel
+                })
+            )
+        )
         .entry(druid::Menu::new(druid::LocalizedString::new("Font Size"))
             .enabled_if(|data: &AppState, _| {
-                data.selection == Selection::Text
+                data.selection == Selection::Text || data.selection == Selection::Callout || data.selection == Selection::Step || data.writing_text.is_some()
             })
             .entry(druid::MenuItem::new("20 pt")
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.font_size = 20.0
+                    data.set_font_size(20.0)
                 })
                 .selected_if(|data: &AppState, _| {
                     data.font_size == 20.0
@@ -245,7 +913,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
             )
             .entry(druid::MenuItem::new("24 pt")
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.font_size = 24.0
+                    data.set_font_size(24.0)
                 })
                 .selected_if(|data: &AppState, _| {
                     data.font_size == 24.0
@@ -253,7 +921,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
             )
             .entry(druid::MenuItem::new("28 pt")
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.font_size = 28.0
+                    data.set_font_size(28.0)
                 })
                 .selected_if(|data: &AppState, _| {
                     data.font_size == 28.0
@@ -261,7 +929,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
             )
             .entry(druid::MenuItem::new("32 pt")
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.font_size = 32.0
+                    data.set_font_size(32.0)
                 })
                 .selected_if(|data: &AppState, _| {
                     data.font_size == 32.0
@@ -269,7 +937,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
             )
             .entry(druid::MenuItem::new("36 pt")
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.font_size = 36.0
+                    data.set_font_size(36.0)
                 })
                 .selected_if(|data: &AppState, _| {
                     data.font_size == 36.0
@@ -277,7 +945,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
             )
             .entry(druid::MenuItem::new("40 pt")
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.font_size = 40.0
+                    data.set_font_size(40.0)
                 })
                 .selected_if(|data: &AppState, _| {
                     data.font_size == 40.0
@@ -285,7 +953,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
             )
             .entry(druid::MenuItem::new("44 pt")
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.font_size = 44.0
+                    data.set_font_size(44.0)
                 })
                 .selected_if(|data: &AppState, _| {
                     data.font_size == 44.0
@@ -293,7 +961,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
             )
             .entry(druid::MenuItem::new("48 pt")
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.font_size = 48.0
+                    data.set_font_size(48.0)
                 })
                 .selected_if(|data: &AppState, _| {
                     data.font_size == 48.0
@@ -301,7 +969,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
             )
             .entry(druid::MenuItem::new("52 pt")
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.font_size = 52.0
+                    data.set_font_size(52.0)
                 })
                 .selected_if(|data: &AppState, _| {
                     data.font_size == 52.0
@@ -309,7 +977,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
             )
             .entry(druid::MenuItem::new("56 pt")
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.font_size = 56.0
+                    data.set_font_size(56.0)
                 })
                 .selected_if(|data: &AppState, _| {
                     data.font_size == 56.0
@@ -317,7 +985,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
             )
             .entry(druid::MenuItem::new("60 pt")
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.font_size = 60.0
+                    data.set_font_size(60.0)
                 })
                 .selected_if(|data: &AppState, _| {
                     data.font_size == 60.0
@@ -325,7 +993,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
             )
             .entry(druid::MenuItem::new("64 pt")
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.font_size = 64.0
+                    data.set_font_size(64.0)
                 })
                 .selected_if(|data: &AppState, _| {
                     data.font_size == 64.0
@@ -333,7 +1001,7 @@ pub fn create_menu() -> druid::Menu<AppState> {
             )
             .entry(druid::MenuItem::new("68 pt")
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.font_size = 68.0
+                    data.set_font_size(68.0)
                 })
                 .selected_if(|data: &AppState, _| {
                     data.font_size == 68.0
@@ -341,61 +1009,260 @@ pub fn create_menu() -> druid::Menu<AppState> {
             )
             .entry(druid::MenuItem::new("72 pt")
                 .on_activate(|_, data: &mut AppState, _| {
-                    data.font_size = 72.0
+                    data.set_font_size(72.0)
                 })
                 .selected_if(|data: &AppState, _| {
                     data.font_size == 72.0
                 })
             )
+        )
+        .entry(druid::Menu::new(druid::LocalizedString::new("Step Style"))
+            .enabled_if(|data: &AppState, _| {
+                data.selection == Selection::Step
+            })
+            .entry(druid::MenuItem::new("1, 2, 3 in Circles")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.step_style = StepStyle::Circle;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.step_style == StepStyle::Circle
+                })
+            )
+            .entry(druid::MenuItem::new("1, 2, 3 in Squares")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.step_style = StepStyle::Square;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.step_style == StepStyle::Square
+                })
+            )
+            .entry(druid::MenuItem::new("A, B, C in Circles")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.step_style = StepStyle::Letters;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.step_style == StepStyle::Letters
+                })
+            )
+        )
+        .entry(druid::Menu::new(druid::LocalizedString::new("Text Style"))
+            .enabled_if(|data: &AppState, _| {
+                data.selection == Selection::Text || data.selection == Selection::Callout || data.writing_text.is_some()
+            })
+            .entry(druid::Menu::new(druid::LocalizedString::new("Background"))
+                .entry(druid::MenuItem::new("None")
+                    .on_activate(|_, data: &mut AppState, _| {
+                        data.update_text_style(|style| style.background = None);
+                    })
+                    .selected_if(|data: &AppState, _| {
+                        data.current_text_style().background.is_none()
+                    })
+                )
+                .entry(druid::MenuItem::new("White")
+                    .on_activate(|_, data: &mut AppState, _| {
+                        data.update_text_style(|style| style.background = Some(Color::WHITE));
+                    })
+                    .selected_if(|data: &AppState, _| {
+                        data.current_text_style().background == Some(Color::WHITE)
+                    })
+                )
+                .entry(druid::MenuItem::new("Black")
+                    .on_activate(|_, data: &mut AppState, _| {
+                        data.update_text_style(|style| style.background = Some(Color::BLACK));
+                    })
+                    .selected_if(|data: &AppState, _| {
+                        data.current_text_style().background == Some(Color::BLACK)
+                    })
+                )
+                .entry(druid::MenuItem::new("Yellow")
+                    .on_activate(|_, data: &mut AppState, _| {
+                        data.update_text_style(|style| style.background = Some(Color::YELLOW));
+                    })
+                    .selected_if(|data: &AppState, _| {
+                        data.current_text_style().background == Some(Color::YELLOW)
+                    })
+                )
+            )
+            .entry(druid::Menu::new(druid::LocalizedString::new("Outline"))
+                .entry(druid::MenuItem::new("None")
+                    .on_activate(|_, data: &mut AppState, _| {
+                        data.update_text_style(|style| style.outline = None);
+                    })
+                    .selected_if(|data: &AppState, _| {
+                        data.current_text_style().outline.is_none()
+                    })
+                )
+                .entry(druid::MenuItem::new("White")
+                    .on_activate(|_, data: &mut AppState, _| {
+                        data.update_text_style(|style| style.outline = Some(Color::WHITE));
+                    })
+                    .selected_if(|data: &AppState, _| {
+                        data.current_text_style().outline == Some(Color::WHITE)
+                    })
+                )
+                .entry(druid::MenuItem::new("Black")
+                    .on_activate(|_, data: &mut AppState, _| {
+                        data.update_text_style(|style| style.outline = Some(Color::BLACK));
+                    })
+                    .selected_if(|data: &AppState, _| {
+                        data.current_text_style().outline == Some(Color::BLACK)
+                    })
+                )
+            )
+            .entry(druid::Menu::new(druid::LocalizedString::new("Padding"))
+                .entry(druid::MenuItem::new("4 pt")
+                    .on_activate(|_, data: &mut AppState, _| {
+                        data.update_text_style(|style| style.padding = 4.0);
+                    })
+                    .selected_if(|data: &AppState, _| {
+                        data.current_text_style().padding == 4.0
+                    })
+                )
+                .entry(druid::MenuItem::new("8 pt")
+                    .on_activate(|_, data: &mut AppState, _| {
+                        data.update_text_style(|style| style.padding = 8.0);
+                    })
+                    .selected_if(|data: &AppState, _| {
+                        data.current_text_style().padding == 8.0
+                    })
+                )
+                .entry(druid::MenuItem::new("12 pt")
+                    .on_activate(|_, data: &mut AppState, _| {
+                        data.update_text_style(|style| style.padding = 12.0);
+                    })
+                    .selected_if(|data: &AppState, _| {
+                        data.current_text_style().padding == 12.0
+                    })
+                )
+            )
+            .entry(druid::Menu::new(druid::LocalizedString::new("Corner Radius"))
+                .entry(druid::MenuItem::new("None")
+                    .on_activate(|_, data: &mut AppState, _| {
+                        data.update_text_style(|style| style.radius = 0.0);
+                    })
+                    .selected_if(|data: &AppState, _| {
+                        data.current_text_style().radius == 0.0
+                    })
+                )
+                .entry(druid::MenuItem::new("4 pt")
+                    .on_activate(|_, data: &mut AppState, _| {
+                        data.update_text_style(|style| style.radius = 4.0);
+                    })
+                    .selected_if(|data: &AppState, _| {
+                        data.current_text_style().radius == 4.0
+                    })
+                )
+                .entry(druid::MenuItem::new("8 pt")
+                    .on_activate(|_, data: &mut AppState, _| {
+                        data.update_text_style(|style| style.radius = 8.0);
+                    })
+                    .selected_if(|data: &AppState, _| {
+                        data.current_text_style().radius == 8.0
+                    })
+                )
+                .entry(druid::MenuItem::new("16 pt")
+                    .on_activate(|_, data: &mut AppState, _| {
+                        data.update_text_style(|style| style.radius = 16.0);
+                    })
+                    .selected_if(|data: &AppState, _| {
+                        data.current_text_style().radius == 16.0
+                    })
+                )
+            )
+            .separator()
+            .entry(druid::MenuItem::new("Align Left")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.update_text_style(|style| style.alignment = TextAlignment::Start);
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.current_text_style().alignment == TextAlignment::Start
+                })
+            )
+            .entry(druid::MenuItem::new("Align Center")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.update_text_style(|style| style.alignment = TextAlignment::Center);
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.current_text_style().alignment == TextAlignment::Center
+                })
+            )
+            .entry(druid::MenuItem::new("Align Right")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.update_text_style(|style| style.alignment = TextAlignment::End);
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.current_text_style().alignment == TextAlignment::End
+                })
+            )
         );
 
     let actions =  druid::Menu::new(druid::LocalizedString::new("Actions"))
         .entry(druid::MenuItem::new(|data: &AppState, _: &Env| {
-            return if let Some(last) = data.actions.last() {
+            if let Some(last) = data.actions.last() {
                 match last {
-                    Action::Pen(_, _, _, _) => { format!("Undo Pen") }
-                    Action::Highlighter(_, _, _, _) => { format!("Undo Highlighter") }
-                    Action::Arrow(_, _, _, _, _) => { format!("Undo Arrow") }
-                    Action::Rectangle(_, _, _, _, _, _) => { format!("Undo Rectangle") }
-                    Action::Circle(_, _, _, _, _, _) => { format!("Undo Circle") }
-                    Action::Ellipse(_, _, _, _, _, _) => { format!("Undo Ellipse") }
-                    Action::Text(_, _, _, _, _) => { format!("Undo Text") }
+                    Action::Pen(_, _, _, _, _, _, _) => { "Undo Pen".to_string() }
+                    Action::Highlighter(_, _, _, _, _) => { "Undo Highlighter".to_string() }
+                    Action::Arrow(_, _, _, _, _, _, _, _) => { "Undo Arrow".to_string() }
+                    Action::Line(_, _, _, _, _, _) => { "Undo Line".to_string() }
+                    Action::Polyline(_, _, _, _, _, _, _) => { "Undo Polyline".to_string() }
+                    Action::Rectangle(_, _, _, _, _, _, _) => { "Undo Rectangle".to_string() }
+                    Action::Circle(_, _, _, _, _, _, _) => { "Undo Circle".to_string() }
+                    Action::RoundedRectangle(_, _, _, _, _, _, _, _) => { "Undo Rounded Rectangle".to_string() }
+                    Action::Ellipse(_, _, _, _, _, _, _) => { "Undo Ellipse".to_string() }
+                    Action::Polygon(_, _, _, _, _, _, _, shape) => { format!("Undo {}", shape.name()) }
+                    Action::Text(_, _, _, _, _, _) => { "Undo Text".to_string() }
+                    Action::Callout(_, _, _, _, _, _, _) => { "Undo Callout".to_string() }
+                    Action::Step(_, _, _, _, _) => { "Undo Step".to_string() }
+                    Action::Magnify(_, _, _, _, _, _, _) => { "Undo Magnifier".to_string() }
+                    Action::Spotlight(_, _, _, _) => { "Undo Spotlight".to_string() }
+                    Action::Stamp(_, _, _, _) => { "Undo Stamp".to_string() }
+                    Action::Erase(_, true) => { "Undo Shape Recognition".to_string() }
+                    Action::Erase(..) => { "Undo Eraser".to_string() }
+                    Action::Trim(..) => { "Undo Auto Trim".to_string() }
                     _ => { "Undo".to_string() }
                 }
             } else { "Undo".to_string() }
         }).hotkey(Some(RawMods::Meta), "Z")
             .on_activate(|_, data: &mut AppState, _| {
-                if let Some(action) =  data.actions.pop() {
-                    data.redo_actions.push(action);
-                }
+                data.undo();
                 data.repaint = true;
             })
             .enabled_if(|data: &AppState, _| {
-                data.actions.len() > 0
+                !data.actions.is_empty()
             })
         )
         .entry(druid::MenuItem::new(|data: &AppState, _: &Env| {
-            return if let Some(last) = data.redo_actions.last() {
+            if let Some(last) = data.redo_actions.last() {
                 match last {
-                    Action::Pen(_, _, _, _) => { format!("Redo Pen") }
-                    Action::Highlighter(_, _, _, _) => { format!("Redo Highlighter") }
-                    Action::Arrow(_, _, _, _, _) => { format!("Redo Arrow") }
-                    Action::Rectangle(_, _, _, _, _, _) => { format!("Redo Rectangle") }
-                    Action::Circle(_, _, _, _, _, _) => { format!("Redo Circle") }
-                    Action::Ellipse(_, _, _, _, _, _) => { format!("Redo Ellipse") }
-                    Action::Text(_, _, _, _, _) => { format!("Redo Text") }
-                    _ => { "Undo".to_string() }
+                    Action::Pen(_, _, _, _, _, _, _) => { "Redo Pen".to_string() }
+                    Action::Highlighter(_, _, _, _, _) => { "Redo Highlighter".to_string() }
+                    Action::Arrow(_, _, _, _, _, _, _, _) => { "Redo Arrow".to_string() }
+                    Action::Line(_, _, _, _, _, _) => { "Redo Line".to_string() }
+                    Action::Polyline(_, _, _, _, _, _, _) => { "Redo Polyline".to_string() }
+                    Action::Rectangle(_, _, _, _, _, _, _) => { "Redo Rectangle".to_string() }
+                    Action::Circle(_, _, _, _, _, _, _) => { "Redo Circle".to_string() }
+                    Action::RoundedRectangle(_, _, _, _, _, _, _, _) => { "Redo Rounded Rectangle".to_string() }
+                    Action::Ellipse(_, _, _, _, _, _, _) => { "Redo Ellipse".to_string() }
+                    Action::Polygon(_, _, _, _, _, _, _, shape) => { format!("Redo {}", shape.name()) }
+                    Action::Text(_, _, _, _, _, _) => { "Redo Text".to_string() }
+                    Action::Callout(_, _, _, _, _, _, _) => { "Redo Callout".to_string() }
+                    Action::Step(_, _, _, _, _) => { "Redo Step".to_string() }
+                    Action::Magnify(_, _, _, _, _, _, _) => { "Redo Magnifier".to_string() }
+                    Action::Spotlight(_, _, _, _) => { "Redo Spotlight".to_string() }
+                    Action::Stamp(_, _, _, _) => { "Redo Stamp".to_string() }
+                    Action::Erase(_, true) => { "Redo Shape Recognition".to_string() }
+                    Action::Erase(..) => { "Redo Eraser".to_string() }
+                    Action::Trim(..) => { "Redo Auto Trim".to_string() }
+                    _ => { "Redo".to_string() }
                 }
             } else { "Redo".to_string() }
         }).hotkey(Some(RawMods::AltMetaShift), "Z")
             .on_activate(|_, data: &mut AppState, _| {
-                if let Some(redo_action) = data.redo_actions.pop() {
-                    data.actions.push(redo_action);
-                }
+                data.redo();
                 data.repaint = true;
             })
             .enabled_if(|data: &AppState, _| {
-                data.redo_actions.len() > 0
+                !data.redo_actions.is_empty()
             })
         )
         .separator()
@@ -405,6 +1272,64 @@ pub fn create_menu() -> druid::Menu<AppState> {
                 data.crop.set(true);
                 data.repaint = true;
             }))
+        .entry(druid::MenuItem::new("Auto Trim").hotkey(Some(RawMods::AltMeta), "T")
+            .on_activate(|_, data: &mut AppState, _| {
+                data.auto_trim();
+            }))
+        .entry(druid::Menu::new(druid::LocalizedString::new("Trim Tolerance"))
+            .entry(druid::MenuItem::new("Exact")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.trim_tolerance = 0;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.trim_tolerance == 0
+                })
+            )
+            .entry(druid::MenuItem::new("Low")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.trim_tolerance = 8;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.trim_tolerance == 8
+                })
+            )
+            .entry(druid::MenuItem::new("High")
+                .on_activate(|_, data: &mut AppState, _| {
+                    data.trim_tolerance = 32;
+                })
+                .selected_if(|data: &AppState, _| {
+                    data.trim_tolerance == 32
+                })
+            )
+        )
+        .entry(druid::MenuItem::new("Image Size...").hotkey(Some(RawMods::AltMeta), "I")
+            .on_activate(|ctx, _: &mut AppState, _| {
+                ctx.submit_command(dialogs::SHOW_IMAGE_SIZE);
+            }))
+        .entry(druid::MenuItem::new("Canvas Size...").hotkey(Some(RawMods::AltMeta), "C")
+            .on_activate(|ctx, _: &mut AppState, _| {
+                ctx.submit_command(dialogs::SHOW_CANVAS_SIZE);
+            }))
+        .entry(druid::MenuItem::new("Border...")
+            .on_activate(|ctx, _: &mut AppState, _| {
+                ctx.submit_command(dialogs::SHOW_BORDER);
+            }))
+        .entry(druid::MenuItem::new("Adjustments...").hotkey(Some(RawMods::AltMeta), "A")
+            .on_activate(|ctx, _: &mut AppState, _| {
+                ctx.submit_command(dialogs::SHOW_ADJUSTMENTS);
+            }))
+        .entry(druid::MenuItem::new("Watermark...")
+            .on_activate(|ctx, _: &mut AppState, _| {
+                ctx.submit_command(dialogs::SHOW_WATERMARK);
+            }))
+        .entry(druid::MenuItem::new("Frame...")
+            .on_activate(|ctx, _: &mut AppState, _| {
+                ctx.submit_command(dialogs::SHOW_FRAME);
+            }))
+        .entry(druid::MenuItem::new("Device Frame...")
+            .on_activate(|ctx, _: &mut AppState, _| {
+                ctx.submit_command(dialogs::SHOW_DEVICE_FRAME);
+            }))
         .entry(druid::MenuItem::new("Flip Vertical ").hotkey(Some(RawMods::Meta), "X")
             .on_activate(|_, data: &mut AppState, _| {
                 data.affine.push(Affine::FLIP_Y);
@@ -417,18 +1342,12 @@ pub fn create_menu() -> druid::Menu<AppState> {
             }));
 
 
-    #[cfg(target_os = "macos")] {
-        return druid::Menu::empty()
-            .entry(about)
-            .entry(file)
-            .entry(tools)
-            .entry(actions);
-    }
-
-    #[cfg(not(target_os="macos"))] {
-        return druid::Menu::empty()
-            .entry(file)
-            .entry(tools)
-            .entry(actions);
-    }
+    #[cfg(target_os = "macos")]
+    let menu = druid::Menu::empty().entry(about);
+    #[cfg(not(target_os="macos"))]
+    let menu = druid::Menu::empty();
+    menu
+        .entry(file)
+        .entry(tools)
+        .entry(actions)
 }
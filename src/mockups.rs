@@ -0,0 +1,213 @@
+use druid::{Data, Lens, Rect};
+use image::{imageops, RgbaImage};
+use crate::stamps::render_svg_to;
+
+#[derive(PartialEq, Debug, Clone, Copy, Data)]
+pub enum DeviceFrame {
+    Phone,
+    Tablet,
+    Browser, // A browser window, its address bar showing the title
+    Terminal,
+}
+
+/// The settings of the Device Frame dialog.
+#[derive(Debug, Clone, Data, Lens)]
+pub struct Mockup {
+    pub frame: DeviceFrame,
+    pub title: String, // Address of the browser window, or title of the terminal one
+}
+
+/// Where the image goes in a device frame, and how large it is scaled to be there.
+pub struct Geometry {
+    pub width: u32,
+    pub height: u32,
+    pub image: Rect, // The image scaled to fit the screen, keeping its proportions
+    pub radius: f64, // Rounding of the corners of the image, when it reaches those of the screen
+}
+
+/// A bundled frame, drawn upright around a screen of a fixed size.
+/// Windows are open at the bottom: their drawing is the title bar, and the screen under it is as wide
+/// as the bar and as high as the image scaled to that width.
+struct Template {
+    svg: &'static str, // `{title}` in it is replaced with the title of the window
+    width: f64,
+    height: f64,
+    screen: Rect,
+    radius: f64, // Rounding of the corners of the screen
+}
+
+const PHONE: Template = Template {
+    svg: include_str!("../frames/phone.svg"),
+    width: 1184.0,
+    height: 2420.0,
+    screen: Rect::new(52.0, 40.0, 1132.0, 2380.0),
+    radius: 100.0,
+};
+
+const TABLET: Template = Template {
+    svg: include_str!("../frames/tablet.svg"),
+    width: 1716.0,
+    height: 2228.0,
+    screen: Rect::new(90.0, 90.0, 1626.0, 2138.0),
+    radius: 30.0,
+};
+
+const BROWSER: Template = Template {
+    svg: include_str!("../frames/browser.svg"),
+    width: 1600.0,
+    height: 84.0,
+    screen: Rect::new(0.0, 84.0, 1600.0, 84.0),
+    radius: 0.0,
+};
+
+const TERMINAL: Template = Template {
+    svg: include_str!("../frames/terminal.svg"),
+    width: 1400.0,
+    height: 64.0,
+    screen: Rect::new(0.0, 64.0, 1400.0, 64.0),
+    radius: 0.0,
+};
+
+fn template(frame: DeviceFrame) -> &'static Template {
+    match frame {
+        DeviceFrame::Phone => &PHONE,
+        DeviceFrame::Tablet => &TABLET,
+        DeviceFrame::Browser => &BROWSER,
+        DeviceFrame::Terminal => &TERMINAL,
+    }
+}
+
+/// The layout of `frame` around an image of the given size.
+pub fn geometry(frame: DeviceFrame, width: u32, height: u32) -> Geometry {
+    let (width, height) = (width as f64, height as f64);
+    let template = template(frame);
+    match frame {
+        DeviceFrame::Phone | DeviceFrame::Tablet => {
+            // devices are laid out upright, then turned when the image is wider than high
+            let landscape = width > height;
+            let (image_width, image_height) = if landscape { (height, width) } else { (width, height) };
+            let screen = template.screen;
+            let scale = (screen.width() / image_width).min(screen.height() / image_height);
+            let (image_width, image_height) = ((image_width * scale).round().min(screen.width()), (image_height * scale).round().min(screen.height()));
+            let (left, top) = (((screen.width() - image_width) / 2f64).floor(), ((screen.height() - image_height) / 2f64).floor());
+            let image = Rect::from_origin_size((screen.x0 + left, screen.y0 + top), (image_width, image_height));
+            // an image reaching into the rounded corners of the screen is rounded with them
+            let radius = if left < template.radius && top < template.radius { template.radius } else { 0f64 };
+            if landscape {
+                // turning clockwise takes the point (x, y) to (height - y, x)
+                let image = Rect::new(template.height - image.y1, image.x0, template.height - image.y0, image.x1);
+                Geometry { width: template.height as u32, height: template.width as u32, image, radius }
+            } else {
+                Geometry { width: template.width as u32, height: template.height as u32, image, radius }
+            }
+        }
+        DeviceFrame::Browser | DeviceFrame::Terminal => {
+            let image_height = (height * template.width / width).round().max(1f64);
+            let image = Rect::from_origin_size(template.screen.origin(), (template.width, image_height));
+            Geometry { width: template.width as u32, height: (template.height + image_height) as u32, image, radius: 0f64 }
+        }
+    }
+}
+
+/// Renders `frame` around an image of the given size, transparent outside of the device.
+/// The image itself is left out, for it to be scaled into the area `geometry` gives.
+pub fn render(frame: DeviceFrame, title: &str, image_width: u32, image_height: u32) -> Option<RgbaImage> {
+    let svg = template(frame).svg.replace("{title}", &escape(title));
+    let mut options = usvg::Options::default();
+    options.fontdb.load_system_fonts();
+    let drawing = render_svg_to(svg.as_bytes(), &options, usvg::FitTo::Original)?.into_rgba8();
+    match frame {
+        DeviceFrame::Phone | DeviceFrame::Tablet if image_width > image_height => Some(imageops::rotate90(&drawing)),
+        DeviceFrame::Phone | DeviceFrame::Tablet => Some(drawing),
+        DeviceFrame::Browser | DeviceFrame::Terminal => {
+            let layout = geometry(frame, image_width, image_height);
+            let mut window = RgbaImage::new(layout.width, layout.height);
+            imageops::replace(&mut window, &drawing, 0, 0);
+            Some(window)
+        }
+    }
+}
+
+/// `text` as it can be put on one line of an SVG document, without the characters XML does not allow.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(' '),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => {}
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_scale_the_image_to_their_width() {
+        for (frame, template) in [(DeviceFrame::Browser, &BROWSER), (DeviceFrame::Terminal, &TERMINAL)] {
+            for (width, height) in [(800, 600), (4000, 3000)] {
+                let layout = geometry(frame, width, height);
+                let image_height = template.width * 0.75;
+                assert_eq!((layout.width, layout.height, layout.radius), (template.width as u32, (template.height + image_height) as u32, 0.0));
+                assert_eq!(layout.image, Rect::new(0.0, template.height, template.width, template.height + image_height));
+            }
+        }
+    }
+
+    #[test]
+    fn devices_scale_the_image_to_their_screen() {
+        for (frame, template) in [(DeviceFrame::Phone, &PHONE), (DeviceFrame::Tablet, &TABLET)] {
+            for (width, height) in [(390, 844), (300, 300), (1024, 768), (2000, 500), (4000, 8000)] {
+                let layout = geometry(frame, width, height);
+                let (image, screen) = (layout.image, template.screen);
+                // devices are turned with the image, keeping their size
+                let landscape = width > height;
+                assert_eq!(layout.width > layout.height, landscape);
+                let upright = if landscape { Rect::new(image.y0, template.height - image.x1, image.y1, template.height - image.x0) } else { image };
+                assert_eq!(screen.union(upright), screen);
+                // the image fills the screen one way, keeping its proportions
+                assert!(upright.width() == screen.width() || upright.height() == screen.height());
+                assert!((image.width() / image.height() - width as f64 / height as f64).abs() < 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn screens_round_images_reaching_their_corners() {
+        assert!(geometry(DeviceFrame::Tablet, 1536, 2048).radius > 0.0);
+        assert!(geometry(DeviceFrame::Tablet, 2048, 1536).radius > 0.0);
+        assert!(geometry(DeviceFrame::Phone, 1170, 2532).radius > 0.0);
+        assert_eq!(geometry(DeviceFrame::Tablet, 300, 300).radius, 0.0);
+    }
+
+    #[test]
+    fn frames_are_rendered_as_large_as_their_layout() {
+        for frame in [DeviceFrame::Phone, DeviceFrame::Tablet, DeviceFrame::Browser, DeviceFrame::Terminal] {
+            for (width, height) in [(600, 400), (400, 600)] {
+                let layout = geometry(frame, width, height);
+                let drawing = render(frame, "https://example.com", width, height).unwrap();
+                assert_eq!(drawing.dimensions(), (layout.width, layout.height));
+                assert_eq!(drawing.get_pixel(0, 0)[3], 0);
+                if matches!(frame, DeviceFrame::Browser | DeviceFrame::Terminal) {
+                    // windows are left open under their title bar
+                    let middle = layout.image.center();
+                    assert_eq!(drawing.get_pixel(middle.x as u32, middle.y as u32)[3], 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn titles_are_escaped_for_svg() {
+        assert_eq!(escape(r#"a < b && "c" > d"#), "a &lt; b &amp;&amp; &quot;c&quot; &gt; d");
+        assert_eq!(escape("one\ntwo\tthree\u{0}\u{1b}\u{ffff}"), "one two three");
+        assert_eq!(escape("café ✓"), "café ✓");
+    }
+}
@@ -1,19 +1,23 @@
-use crate::utils::{AppState, Action};
-use druid::{Cursor, Rect, Widget, Code, TextLayout, ImageBuf, Affine, FontDescriptor, FontFamily};
+use crate::utils::{transform, AppState, Action, ArrowHead, ArrowStyle, ArrowTail, EraserMode, HighlighterTip, Shape, StepStyle, TextStyle};
+use druid::{Cursor, Rect, Widget, Code, TextLayout, ImageBuf, Affine, FontDescriptor, FontFamily, WidgetPod, BoxConstraints, Insets, KbKey, Modifiers, Size};
 use druid::RenderContext;
 use druid::{Env, Color};
-use druid::{Data, Lens};
-use druid::kurbo::{Circle, Line, Point, Vec2, Ellipse};
-use druid::piet::{ImageFormat, InterpolationMode, StrokeStyle};
+use druid::kurbo::{BezPath, Circle, Line, ParamCurve, ParamCurveArclen, ParamCurveNearest, Point, QuadBez, Shape as _, Vec2, Ellipse};
+use druid::text::{Selection, TextComponent};
+use druid::widget::TextBox;
+use druid::theme;
+use druid::piet::{Device, ImageFormat, InterpolationMode, LineCap, LineJoin, Piet, StrokeStyle};
 use druid::Event;
 use image::{GenericImageView, DynamicImage};
 use num_traits::cast::FromPrimitive;
 use druid::Screen as dScreen;
 use screenshots::Screen;
 use crate::utils;
+use crate::dialogs;
+use crate::watermark::WatermarkPosition;
 
 fn calculate_arrowhead(start: Point, end: Point, arrowhead_length: f64, arrowhead_width: f64) -> (Point, Point) {
-    let direction = (end - start).normalize();
+    let direction = if start == end { Vec2::new(1.0, 0.0) } else { (end - start).normalize() };
     let perpendicular = Vec2::new(-direction.y, direction.x) * arrowhead_width / 2.0;
     let arrowhead_base = end - direction * arrowhead_length;
     let left_point = arrowhead_base + perpendicular;
@@ -21,116 +25,1358 @@ fn calculate_arrowhead(start: Point, end: Point, arrowhead_length: f64, arrowhea
     (left_point, right_point)
 }
 
-#[derive(Clone, Data, Lens)]
-struct TextInputState {
-    text: String,
+/// The length of arrowheads, which grows with the stroke so they stay readable at any arrow length.
+fn arrowhead_length(stroke: f64) -> f64 {
+    3f64 * stroke + 8f64
 }
 
+/// Draws an arrowhead at `tip`, pointing away from `from`.
+fn draw_arrowhead(rc: &mut Piet, from: Point, tip: Point, color: &Color, stroke: f64, head: ArrowHead) {
+    let length = arrowhead_length(stroke);
+    let (left_point, right_point) = calculate_arrowhead(from, tip, length, length * 0.8);
+    match head {
+        ArrowHead::Open => {
+            rc.stroke(Line::new(left_point, tip), color, stroke);
+            rc.stroke(Line::new(right_point, tip), color, stroke);
+        }
+        ArrowHead::Filled => {
+            let mut path = BezPath::new();
+            path.move_to(left_point);
+            path.line_to(tip);
+            path.line_to(right_point);
+            path.close_path();
+            rc.fill(path, color);
+        }
+    }
+}
 
-pub struct DrawingWidget;
+/// The point in the middle of an arrow, which is dragged to bend curved arrows.
+fn arrow_handle(start: Point, end: Point, control: Point) -> Point {
+    QuadBez::new(start, control, end).eval(0.5)
+}
 
-impl Widget<AppState> for DrawingWidget {
-    fn event(&mut self, ctx: &mut druid::EventCtx, event: &Event, data: &mut AppState, _env: &Env) {
-        // Handle user input events for drawing here
-        match event {
-            Event::KeyDown(key) => {
-                if data.is_writing_text {
-                    if key.code.eq(&Code::Enter) {
-                        data.is_writing_text = false;
-                        return;
-                    }
-                    if let Some(action) = data.actions.last_mut() {
-                        if let Action::Text(affine, _, string, _, _) = action {
-                            *affine = data.affine.clone();
-                            if key.code.eq(&Code::Backspace) {
-                                if !string.is_empty() {
-                                    string.pop();
-                                }
-                            } else if key.code.eq(&Code::ShiftLeft) ||
-                                key.code.eq(&Code::ShiftRight) ||
-                                key.code.eq(&Code::Tab) ||
-                                key.code.eq(&Code::MetaLeft) ||
-                                key.code.eq(&Code::MetaRight) ||
-                                key.code.eq(&Code::AltLeft) ||
-                                key.code.eq(&Code::AltRight) {} else {
-                                let char = key.key.to_string();
-                                string.push(char.chars().next().unwrap());
+/// Snaps `end` so that the segment from `start` is horizontal, vertical or diagonal.
+fn snap_angle(start: Point, end: Point) -> Point {
+    let delta = end - start;
+    let step = std::f64::consts::FRAC_PI_4;
+    start + Vec2::from_angle((delta.atan2() / step).round() * step) * delta.hypot()
+}
+
+/// The corners of a box dragged from `anchor` to `pos`, made square with `square`
+/// and spanning both sides of the anchor with `centered`.
+fn constrain(anchor: Point, pos: Point, square: bool, centered: bool) -> (Point, Point) {
+    let mut delta = pos - anchor;
+    if square {
+        let side = delta.x.abs().max(delta.y.abs());
+        delta = Vec2::new(side.copysign(delta.x), side.copysign(delta.y));
+    }
+    if centered { (anchor - delta, anchor + delta) } else { (anchor, anchor + delta) }
+}
+
+/// Updates the shape being drawn from `anchor` as the mouse moves to `pos`.
+/// Shift keeps boxes square and lines at multiples of 45°, Alt draws boxes from their center.
+fn drag_shape(action: &mut Action, anchor: Point, pos: Point, mods: Modifiers) {
+    match action {
+        Action::Rectangle(_, start_point, end_point, _, _, _, _)
+        | Action::RoundedRectangle(_, start_point, end_point, _, _, _, _, _)
+        | Action::Ellipse(_, start_point, end_point, _, _, _, _)
+        | Action::Polygon(_, start_point, end_point, _, _, _, _, _)
+        | Action::Magnify(_, start_point, end_point, _, _, _, _)
+        | Action::Crop(_, start_point, end_point) => {
+            (*start_point, *end_point) = constrain(anchor, pos, mods.shift(), mods.alt());
+        }
+        Action::Spotlight(_, areas, _, _) => {
+            if let Some((start_point, end_point, _)) = areas.last_mut() {
+                (*start_point, *end_point) = constrain(anchor, pos, mods.shift(), mods.alt());
+            }
+        }
+        // dragging away from where a stamp is placed sizes it, a plain click keeps the chosen size
+        Action::Stamp(_, center, width, image) if pos.distance(*center) > 4f64 => {
+            *width = stamp_width(*center, pos, image);
+        }
+        Action::Circle(_, center, radius, _, _, _, _) => {
+            *radius = center.distance(pos);
+        }
+        Action::Arrow(_, start_point, end_point, _, _, _, _, control) => {
+            *end_point = if mods.shift() { snap_angle(*start_point, pos) } else { pos };
+            if let Some(control) = control {
+                *control = start_point.midpoint(*end_point);
+            }
+        }
+        Action::Line(_, start_point, end_point, _, _, _) => {
+            *end_point = if mods.shift() { snap_angle(*start_point, pos) } else { pos };
+        }
+        _ => {}
+    }
+}
+
+/// Moves the shape being drawn by `delta`.
+fn nudge_shape(action: &mut Action, delta: Vec2) {
+    match action {
+        Action::Rectangle(_, start_point, end_point, _, _, _, _)
+        | Action::RoundedRectangle(_, start_point, end_point, _, _, _, _, _)
+        | Action::Ellipse(_, start_point, end_point, _, _, _, _)
+        | Action::Polygon(_, start_point, end_point, _, _, _, _, _)
+        | Action::Line(_, start_point, end_point, _, _, _)
+        | Action::Magnify(_, start_point, end_point, _, _, _, _)
+        | Action::Crop(_, start_point, end_point) => {
+            *start_point += delta;
+            *end_point += delta;
+        }
+        Action::Circle(_, center, _, _, _, _, _) | Action::Stamp(_, center, _, _) => {
+            *center += delta;
+        }
+        Action::Spotlight(_, areas, _, _) => {
+            if let Some((start_point, end_point, _)) = areas.last_mut() {
+                *start_point += delta;
+                *end_point += delta;
+            }
+        }
+        Action::Arrow(_, start_point, end_point, _, _, _, _, control) => {
+            *start_point += delta;
+            *end_point += delta;
+            if let Some(control) = control {
+                *control += delta;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A smooth curve through `points`, each segment being the Bézier equivalent of a Catmull-Rom spline.
+fn smooth_path(points: &[Point]) -> BezPath {
+    let mut path = BezPath::new();
+    path.move_to(points[0]);
+    for i in 1..points.len() {
+        let before = points[i.saturating_sub(2)];
+        let start = points[i - 1];
+        let end = points[i];
+        let after = points[(i + 1).min(points.len() - 1)];
+        path.curve_to(start + (end - before) / 6f64, end - (after - start) / 6f64, end);
+    }
+    path
+}
+
+/// Indices of the points to keep so that the line through them stays within `tolerance`
+/// of all of `points` (Ramer-Douglas-Peucker).
+fn simplify(points: &[Point], tolerance: f64) -> Vec<usize> {
+    if points.len() < 3 {
+        return (0..points.len()).collect();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut ranges = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = ranges.pop() {
+        let line = Line::new(points[first], points[last]);
+        let farthest = (first + 1..last)
+            .map(|i| (i, line.nearest(points[i], 1e-6).distance_sq))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((index, distance_sq)) = farthest {
+            if distance_sq > tolerance * tolerance {
+                keep[index] = true;
+                ranges.push((first, index));
+                ranges.push((index, last));
+            }
+        }
+    }
+    (0..points.len()).filter(|i| keep[*i]).collect()
+}
+
+/// The width of the pen at `to`, thinner the faster the mouse moved there from `from`.
+fn pen_width(stroke: f64, previous: f64, from: Point, to: Point) -> f64 {
+    let target = stroke * (1.5 - from.distance(to) / 30f64).clamp(0.4, 1.5);
+    // ease towards the new width, so it does not jump between mouse events
+    previous * 0.7 + target * 0.3
+}
+
+/// The length of the line through `points`.
+fn path_length(points: &[Point]) -> f64 {
+    points.windows(2).map(|pair| pair[0].distance(pair[1])).sum()
+}
+
+/// The clean shape a freehand pen stroke was meant to be, if it looks like a rectangle, an ellipse,
+/// an arrow or a straight line.
+fn recognize_shape(action: &Action, arrow_style: ArrowStyle) -> Option<Action> {
+    let Action::Pen(affine, points, color, stroke, line_style, _, _) = action else { return None };
+    let length = path_length(points);
+    if points.len() < 2 || length < 20f64 {
+        return None;
+    }
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let bounds = points.iter().fold(Rect::from_points(first, first), |bounds, point| bounds.union_pt(*point));
+    // closed strokes: compare how far the points are from the outline of each candidate, relative to its size
+    if first.distance(last) < length * 0.2 && bounds.width() > 10f64 && bounds.height() > 10f64 {
+        let center = bounds.center();
+        let (rx, ry) = (bounds.width() / 2f64, bounds.height() / 2f64);
+        let ellipse_error = points.iter()
+            .map(|p| (((p.x - center.x) / rx).powi(2) + ((p.y - center.y) / ry).powi(2)).sqrt() - 1f64)
+            .map(f64::abs)
+            .sum::<f64>() / points.len() as f64;
+        let rectangle_error = points.iter()
+            .map(|p| (p.x - bounds.x0).abs().min((bounds.x1 - p.x).abs()).min((p.y - bounds.y0).abs()).min((bounds.y1 - p.y).abs()))
+            .sum::<f64>() / points.len() as f64 / rx.min(ry);
+        if rectangle_error < ellipse_error && rectangle_error < 0.1 {
+            return Some(Action::Rectangle(affine.clone(), bounds.origin(), Point::new(bounds.x1, bounds.y1), *color, None, *stroke, *line_style));
+        }
+        if ellipse_error < 0.1 {
+            return Some(Action::Ellipse(affine.clone(), bounds.origin(), Point::new(bounds.x1, bounds.y1), *color, None, *stroke, *line_style));
+        }
+        return None;
+    }
+    // arrows: a straight shaft up to the point farthest from the start, then a short head drawn around it
+    let tip = (0..points.len()).max_by(|a, b| first.distance(points[*a]).total_cmp(&first.distance(points[*b])))?;
+    let shaft = first.distance(points[tip]);
+    if shaft / path_length(&points[..=tip]) > 0.95 {
+        let head = path_length(&points[tip..]);
+        if head < shaft * 0.1 {
+            return Some(Action::Line(affine.clone(), first, points[tip], *color, *stroke, *line_style));
+        }
+        if head < shaft * 0.8 && points[tip..].iter().all(|p| p.distance(points[tip]) < shaft * 0.35) {
+            let style = ArrowStyle { curved: false, ..arrow_style };
+            return Some(Action::Arrow(affine.clone(), first, points[tip], *color, *stroke, *line_style, style, None));
+        }
+    }
+    None
+}
+
+/// The area swept by a chisel tip of the given width along `points`, one parallelogram per segment.
+/// They all turn the same way, so filling the path paints their overlaps only once.
+fn chisel_path(points: &[Point], width: f64) -> BezPath {
+    let nib = Vec2::new(1f64, -1f64).normalize() * width / 2f64;
+    let mut path = BezPath::new();
+    for pair in points.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        let mut corners = [start - nib, end - nib, end + nib, start + nib];
+        if (end - start).cross(nib) < 0f64 {
+            corners.reverse();
+        }
+        path.move_to(corners[0]);
+        for corner in &corners[1..] {
+            path.line_to(*corner);
+        }
+        path.close_path();
+    }
+    path
+}
+
+/// Ends the polyline being drawn, dropping the vertex that follows the mouse.
+fn finish_polyline(data: &mut AppState) {
+    data.is_drawing_polyline = false;
+    if let Some(Action::Polyline(_, points, _, _, _, _, _)) = data.actions.last_mut() {
+        points.pop();
+        if points.len() < 2 {
+            data.actions.pop();
+        }
+    }
+    data.repaint = true;
+}
+
+/// The outline of `shape`, stretched to fill the rectangle between `start` and `end`.
+fn shape_path(shape: Shape, start: Point, end: Point) -> BezPath {
+    let (corners, inner) = match shape {
+        Shape::Triangle => (3, None),
+        Shape::Diamond => (4, None),
+        Shape::Star => (5, Some(0.382)),
+        Shape::Regular(sides) => (sides.max(3), None),
+    };
+    // vertices on the unit circle, the first one pointing up
+    let step = std::f64::consts::TAU / corners as f64;
+    let mut vertices = Vec::new();
+    for i in 0..corners {
+        let angle = i as f64 * step - std::f64::consts::FRAC_PI_2;
+        vertices.push(Vec2::from_angle(angle).to_point());
+        if let Some(radius) = inner {
+            vertices.push((Vec2::from_angle(angle + step / 2f64) * radius).to_point());
+        }
+    }
+    let bounds = vertices.iter().fold(Rect::from_points(vertices[0], vertices[0]), |bounds, v| bounds.union_pt(*v));
+    let rect = Rect::from_points(start, end);
+    let mut path = BezPath::new();
+    for (i, v) in vertices.iter().enumerate() {
+        let point = Point::new(
+            rect.x0 + (v.x - bounds.x0) / bounds.width() * rect.width(),
+            rect.y0 + (v.y - bounds.y0) / bounds.height() * rect.height(),
+        );
+        if i == 0 { path.move_to(point); } else { path.line_to(point); }
+    }
+    path.close_path();
+    path
+}
+
+fn text_layout(text: &str, color: Color, font_size: f64, style: &TextStyle) -> TextLayout<String> {
+    let mut layout = TextLayout::<String>::from_text(text.to_string());
+    layout.set_font(FontDescriptor::new(FontFamily::SYSTEM_UI).with_size(font_size));
+    layout.set_text_color(color);
+    layout.set_text_alignment(style.alignment);
+    if let Some(width) = style.width {
+        layout.set_wrap_width(width);
+    }
+    layout
+}
+
+/// The box of a text annotation, padding included.
+fn text_bounds(layout: &TextLayout<String>, style: &TextStyle, position: Point) -> Rect {
+    let size = Size::new(style.width.unwrap_or(layout.size().width), layout.size().height);
+    Rect::from_origin_size(position, size).inflate(style.padding, style.padding)
+}
+
+/// Draws the halo around the glyphs of a text annotation.
+fn draw_outline(rc: &mut Piet, text: &str, font_size: f64, style: &TextStyle, position: Point, env: &Env) {
+    if let Some(outline) = style.outline {
+        let mut halo = text_layout(text, outline, font_size, style);
+        halo.rebuild_if_needed(rc.text(), env);
+        let thickness = (font_size / 16f64).max(1f64);
+        if let Some(halo) = halo.layout() {
+            for step in 0..16 {
+                let angle = step as f64 * std::f64::consts::PI / 8f64;
+                rc.draw_text(halo, position + Vec2::from_angle(angle) * thickness);
+            }
+        }
+    }
+}
+
+/// The tail of a callout, from the middle of its box to the pointed location.
+fn callout_tail(bounds: Rect, tail: Point) -> BezPath {
+    let center = bounds.center();
+    let direction = if tail == center { Vec2::new(0.0, 1.0) } else { (tail - center).normalize() };
+    let base = Vec2::new(-direction.y, direction.x) * bounds.width().min(bounds.height()) / 4f64;
+    let mut path = BezPath::new();
+    path.move_to(center + base);
+    path.line_to(tail);
+    path.line_to(center - base);
+    path.close_path();
+    path
+}
+
+/// The part of an annotation being dragged.
+#[derive(Clone, Copy, PartialEq)]
+enum Handle {
+    Point, // The tail of a callout or the middle of a curved arrow
+    Source, // The area a magnifier enlarges
+    Inset, // The enlarged copy of that area
+    Body, // A whole stamp
+    Corner, // The corner of a stamp, dragged to resize it
+}
+
+/// The box a stamp of the given width is drawn in, keeping the proportions of its image.
+fn stamp_rect(center: Point, width: f64, image: &DynamicImage) -> Rect {
+    let height = width * image.height() as f64 / image.width().max(1) as f64;
+    Rect::from_center_size(center, Size::new(width, height))
+}
+
+/// The width of a stamp centered on `center` whose box reaches `corner`.
+fn stamp_width(center: Point, corner: Point, image: &DynamicImage) -> f64 {
+    let aspect = image.width() as f64 / image.height().max(1) as f64;
+    let delta = corner - center;
+    (delta.x.abs() * 2f64).max(delta.y.abs() * 2f64 * aspect).max(8f64)
+}
+
+/// The enlarged copy of the area between `start` and `end`, centered on `inset`.
+fn magnifier_inset(start: Point, end: Point, inset: Point, zoom: f64) -> Rect {
+    Rect::from_center_size(inset, Rect::from_points(start, end).size() * zoom)
+}
+
+/// The outline of a magnifier area, round or not.
+fn magnifier_outline(rect: Rect, round: bool) -> BezPath {
+    if round { Ellipse::from_rect(rect).into_path(0.1) } else { rect.into_path(0.1) }
+}
+
+/// The point where the outline of `rect`, or of the ellipse in it, meets the line from its center towards `toward`.
+fn edge_point(rect: Rect, toward: Point, round: bool) -> Point {
+    let direction = toward - rect.center();
+    if direction.hypot() == 0f64 {
+        return rect.center();
+    }
+    let (rx, ry) = (rect.width() / 2f64, rect.height() / 2f64);
+    let scale = if round {
+        1f64 / ((direction.x / rx).powi(2) + (direction.y / ry).powi(2)).sqrt()
+    } else {
+        (rx / direction.x.abs()).min(ry / direction.y.abs())
+    };
+    rect.center() + direction * scale
+}
+
+/// Finds the annotation whose handle, for the selected tool, is under `pos`.
+fn hit_handle(data: &AppState, pos: Point, size: Size) -> Option<(usize, Handle)> {
+    let global = transform(&data.affine, size);
+    for (index, action) in data.visible_actions().into_iter().rev() {
+        match (&data.selection, action) {
+            (utils::Selection::Callout, Action::Callout(affine, _, _, _, _, _, tail)) if (global * transform(affine, size) * *tail).distance(pos) < 10f64 => {
+                return Some((index, Handle::Point));
+            }
+            (utils::Selection::Arrow, Action::Arrow(affine, start_point, end_point, _, _, _, _, Some(control))) if (global * transform(affine, size) * arrow_handle(*start_point, *end_point, *control)).distance(pos) < 10f64 => {
+                return Some((index, Handle::Point));
+            }
+            (utils::Selection::Stamp, Action::Stamp(affine, center, width, image)) => {
+                let local = global * transform(affine, size);
+                let rect = stamp_rect(*center, *width, image);
+                if (local * Point::new(rect.x1, rect.y1)).distance(pos) < 10f64 {
+                    return Some((index, Handle::Corner));
+                }
+                if rect.contains(local.inverse() * pos) {
+                    return Some((index, Handle::Body));
+                }
+            }
+            (utils::Selection::Magnifier, Action::Magnify(affine, start_point, end_point, Some(inset), _, _, style)) => {
+                let local = (global * transform(affine, size)).inverse() * pos;
+                if magnifier_inset(*start_point, *end_point, *inset, style.zoom).contains(local) {
+                    return Some((index, Handle::Inset));
+                }
+                if Rect::from_points(*start_point, *end_point).contains(local) {
+                    return Some((index, Handle::Source));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns the text of the annotation currently being written, if any.
+fn editing_text(data: &AppState) -> String {
+    if let Some(Action::Text(_, _, text, _, _, _) | Action::Callout(_, _, text, _, _, _, _)) = data.writing_text.and_then(|index| data.actions.get(index)) {
+        return text.to_string();
+    }
+    String::new()
+}
+
+/// Leaves text editing mode, discarding the annotation if nothing was typed.
+fn finish_text(data: &mut AppState) {
+    data.is_drawing = false;
+    if let Some(index) = data.writing_text.take() {
+        if let Some(Action::Text(_, _, text, _, _, _) | Action::Callout(_, _, text, _, _, _, _)) = data.actions.get(index) {
+            if text.is_empty() {
+                if index + 1 == data.actions.len() {
+                    data.actions.remove(index);
+                } else {
+                    // erase actions refer to the ones before them by index, so earlier annotations are erased instead
+                    data.push_action(Action::Erase(vec![(index, Vec::new())], false));
+                }
+            }
+        }
+    }
+    data.repaint = true;
+}
+
+/// Finds the topmost text annotation under `pos`, along with the text position of the click.
+fn hit_text(ctx: &mut druid::EventCtx, data: &AppState, pos: Point, env: &Env) -> Option<(usize, usize)> {
+    let global = transform(&data.affine, ctx.size());
+    for (index, action) in data.visible_actions().into_iter().rev() {
+        if let Action::Text(affine, position, text, color, font_size, style) | Action::Callout(affine, position, text, color, font_size, style, _) = action {
+            let mut layout = text_layout(text, *color, *font_size, style);
+            layout.rebuild_if_needed(ctx.text(), env);
+            let local = (global * transform(affine, ctx.size())).inverse() * pos;
+            if text_bounds(&layout, style, *position).contains(local) {
+                return Some((index, layout.text_position_for_point(local - position.to_vec2())));
+            }
+        }
+    }
+    None
+}
+
+/// Whether `pos` is within `distance` of the line through `points`.
+fn near_points(points: &[Point], pos: Point, distance: f64) -> bool {
+    points.iter().any(|point| point.distance(pos) <= distance)
+        || points.windows(2).any(|pair| Line::new(pair[0], pair[1]).nearest(pos, 1e-6).distance_sq <= distance * distance)
+}
+
+/// Whether `pos` is within `distance` of the outline of `shape`, or inside it when it is filled.
+fn near_shape(shape: impl druid::kurbo::Shape, filled: bool, pos: Point, distance: f64) -> bool {
+    (filled && shape.contains(pos)) || shape.path_segments(0.1).any(|segment| segment.nearest(pos, 1e-3).distance_sq <= distance * distance)
+}
+
+/// Cuts a stroke where it comes within `distance` of `pos`, returning the pieces left along with their widths.
+fn split_stroke(points: &[Point], widths: &[f64], pos: Point, distance: f64) -> Vec<(Vec<Point>, Vec<f64>)> {
+    let varying = widths.len() == points.len();
+    // add points along long segments, so the eraser cuts them where it crosses them
+    let mut dense = vec![(points[0], widths.first().copied())];
+    for i in 1..points.len() {
+        let steps = (points[i - 1].distance(points[i]) / (distance / 2f64)).ceil().max(1f64) as usize;
+        for step in 1..=steps {
+            let t = step as f64 / steps as f64;
+            let width = if varying { Some(widths[i - 1] + (widths[i] - widths[i - 1]) * t) } else { None };
+            dense.push((points[i - 1].lerp(points[i], t), width));
+        }
+    }
+    let mut pieces = Vec::new();
+    let mut piece: (Vec<Point>, Vec<f64>) = (Vec::new(), Vec::new());
+    for (point, width) in dense {
+        if point.distance(pos) > distance {
+            piece.0.push(point);
+            piece.1.extend(width);
+        } else if !piece.0.is_empty() {
+            pieces.push(std::mem::take(&mut piece));
+        }
+    }
+    if !piece.0.is_empty() {
+        pieces.push(piece);
+    }
+    pieces.into_iter()
+        .filter(|(points, _)| points.len() > 1)
+        .map(|(points, widths)| {
+            let kept = simplify(&points, 0.5);
+            let widths = if varying { kept.iter().map(|i| widths[*i]).collect() } else { widths };
+            (kept.iter().map(|i| points[*i]).collect(), widths)
+        })
+        .collect()
+}
+
+/// What is left of an annotation after erasing at `pos`, in its own coordinates, or `None` when the eraser misses it.
+fn erase_action(ctx: &mut druid::EventCtx, action: &Action, mode: EraserMode, pos: Point, radius: f64, env: &Env) -> Option<Vec<Action>> {
+    let touched = match action {
+        Action::Pen(_, points, _, stroke, _, _, _) => near_points(points, pos, radius + stroke / 2f64),
+        Action::Highlighter(_, points, _, stroke, _) => near_points(points, pos, radius + stroke * 1.5),
+        Action::Rectangle(_, start_point, end_point, _, fill, stroke, _) => near_shape(Rect::from_points(*start_point, *end_point), fill.is_some(), pos, radius + stroke / 2f64),
+        Action::RoundedRectangle(_, start_point, end_point, _, fill, stroke, _, corner) => near_shape(Rect::from_points(*start_point, *end_point).to_rounded_rect(*corner), fill.is_some(), pos, radius + stroke / 2f64),
+        Action::Circle(_, center, circle_radius, _, fill, stroke, _) => near_shape(Circle::new(*center, *circle_radius), fill.is_some(), pos, radius + stroke / 2f64),
+        Action::Ellipse(_, start_point, end_point, _, fill, stroke, _) => near_shape(Ellipse::from_rect(Rect::from_points(*start_point, *end_point)), fill.is_some(), pos, radius + stroke / 2f64),
+        Action::Polygon(_, start_point, end_point, _, fill, stroke, _, shape) => near_shape(shape_path(*shape, *start_point, *end_point), fill.is_some(), pos, radius + stroke / 2f64),
+        Action::Polyline(_, points, _, closed, fill, stroke, _) => {
+            let mut path = BezPath::new();
+            path.move_to(points[0]);
+            for point in &points[1..] {
+                path.line_to(*point);
+            }
+            if *closed {
+                path.close_path();
+            }
+            near_shape(path, *closed && fill.is_some(), pos, radius + stroke / 2f64)
+        }
+        Action::Arrow(_, start_point, end_point, _, stroke, _, _, control) => {
+            let curve = QuadBez::new(*start_point, control.unwrap_or_else(|| start_point.midpoint(*end_point)), *end_point);
+            curve.nearest(pos, 1e-3).distance_sq.sqrt() <= radius + stroke / 2f64
+        }
+        Action::Line(_, start_point, end_point, _, stroke, _) => Line::new(*start_point, *end_point).nearest(pos, 1e-6).distance_sq.sqrt() <= radius + stroke / 2f64,
+        Action::Text(_, position, text, color, font_size, style) | Action::Callout(_, position, text, color, font_size, style, _) => {
+            let mut layout = text_layout(text, *color, *font_size, style);
+            layout.rebuild_if_needed(ctx.text(), env);
+            let bounds = text_bounds(&layout, style, *position);
+            let tail = if let Action::Callout(_, _, _, _, _, _, tail) = action { callout_tail(bounds, *tail).contains(pos) } else { false };
+            bounds.inflate(radius, radius).contains(pos) || tail
+        }
+        Action::Step(_, center, _, font_size, _) => center.distance(pos) <= font_size * 0.75 + radius,
+        Action::Magnify(_, start_point, end_point, inset, _, _, style) => {
+            Rect::from_points(*start_point, *end_point).inflate(radius, radius).contains(pos)
+                || inset.is_some_and(|inset| magnifier_inset(*start_point, *end_point, inset, style.zoom).inflate(radius, radius).contains(pos))
+        }
+        Action::Stamp(_, center, width, image) => stamp_rect(*center, *width, image).inflate(radius, radius).contains(pos),
+        Action::Spotlight(_, areas, ..) => areas.iter().any(|(start_point, end_point, _)| Rect::from_points(*start_point, *end_point).inflate(radius, radius).contains(pos)),
+        Action::Erase(..) | Action::Crop(..) | Action::Trim(..) => false,
+    };
+    if !touched {
+        return None;
+    }
+    Some(left_after_erasing(action, mode, pos, radius))
+}
+
+/// What is left of an annotation the eraser touched at `pos`: the pieces of cut strokes,
+/// the other areas of a spotlight, and nothing of the rest.
+fn left_after_erasing(action: &Action, mode: EraserMode, pos: Point, radius: f64) -> Vec<Action> {
+    match (mode, action) {
+        (EraserMode::Stroke, Action::Pen(affine, points, color, stroke, line_style, smooth, widths)) => {
+            split_stroke(points, widths, pos, radius + stroke / 2f64).into_iter()
+                .map(|(points, widths)| Action::Pen(affine.clone(), points, *color, *stroke, *line_style, *smooth, widths))
+                .collect()
+        }
+        (EraserMode::Stroke, Action::Highlighter(affine, points, color, stroke, tip)) => {
+            split_stroke(points, &[], pos, radius + stroke * 1.5).into_iter()
+                .map(|(points, _)| Action::Highlighter(affine.clone(), points, *color, *stroke, *tip))
+                .collect()
+        }
+        // erasing an area leaves the others bright, and an empty spotlight still hides the previous ones
+        (_, Action::Spotlight(affine, areas, dim, blur)) => {
+            let areas = areas.iter().filter(|(start_point, end_point, _)| !Rect::from_points(*start_point, *end_point).inflate(radius, radius).contains(pos)).copied().collect();
+            vec![Action::Spotlight(affine.clone(), areas, *dim, *blur)]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Erases the annotations under `pos`, recording what is left of them in the erase action being drawn.
+fn erase(ctx: &mut druid::EventCtx, data: &mut AppState, pos: Point, env: &Env) {
+    let size = ctx.size();
+    let global = transform(&data.affine, size);
+    let radius = data.stroke * 4f64;
+    let visible = data.visible_actions();
+    let mut changes = Vec::new();
+    // an annotation the stroke eraser already cut has several pieces, all sharing its index
+    for group in visible.chunk_by(|a, b| a.0 == b.0) {
+        let mut left = Vec::new();
+        let mut changed = false;
+        for (_, action) in group {
+            let local = match action.affine() {
+                Some(affine) => (global * transform(affine, size)).inverse() * pos,
+                None => continue,
+            };
+            match erase_action(ctx, action, data.eraser_mode, local, radius, env) {
+                Some(pieces) => {
+                    changed = true;
+                    left.extend(pieces);
+                }
+                None => left.push((*action).clone()),
+            }
+        }
+        if changed {
+            changes.push((group[0].0, left));
+        }
+    }
+    if let Some(Action::Erase(erased, _)) = data.actions.last_mut() {
+        for (index, left) in changes {
+            match erased.iter_mut().find(|(changed, _)| *changed == index) {
+                Some(entry) => entry.1 = left,
+                None => erased.push((index, left)),
+            }
+        }
+    }
+}
+
+/// Runs `f` with the state of the render context saved, like `PaintCtx::with_save`.
+fn with_save(rc: &mut Piet, f: impl FnOnce(&mut Piet)) {
+    if rc.save().is_ok() {
+        f(rc);
+        rc.restore().ok();
+    }
+}
+
+/// Paints the watermark over the annotations, which it is never flipped with.
+fn paint_watermark(rc: &mut Piet, data: &AppState, size: Size, env: &Env) {
+    let mark = &data.watermark;
+    if !mark.enabled {
+        return;
+    }
+    // the watermark is drawn in its own coordinates, then scaled to the chosen width
+    let (image, layout) = match (&mark.image, mark.use_image) {
+        (Some(image), true) => {
+            let mut pixels = image.to_rgba8();
+            pixels.pixels_mut().for_each(|pixel| pixel[3] = (pixel[3] as f64 * mark.opacity).round() as u8);
+            (rc.make_image(pixels.width() as usize, pixels.height() as usize, pixels.as_raw(), ImageFormat::RgbaSeparate).ok(), None)
+        }
+        _ => {
+            let color = mark.color.color(data.color).with_alpha(mark.opacity);
+            let mut layout = text_layout(&mark.text, color, 48f64, &TextStyle::default());
+            layout.rebuild_if_needed(rc.text(), env);
+            (None, Some(layout))
+        }
+    };
+    let content = match (&image, &layout, &mark.image) {
+        (Some(_), _, Some(image)) => Size::new(image.width() as f64, image.height() as f64),
+        (_, Some(layout), _) => layout.size(),
+        _ => return,
+    };
+    if content.width <= 0f64 || content.height <= 0f64 {
+        return;
+    }
+    let scale = size.width * mark.size / 100f64 / content.width;
+    let (width, height) = (content.width * scale, content.height * scale);
+    let margin = size.width.min(size.height) * 0.03;
+    let centers = match mark.position {
+        WatermarkPosition::TopLeft => vec![Point::new(margin + width / 2f64, margin + height / 2f64)],
+        WatermarkPosition::TopRight => vec![Point::new(size.width - margin - width / 2f64, margin + height / 2f64)],
+        WatermarkPosition::BottomLeft => vec![Point::new(margin + width / 2f64, size.height - margin - height / 2f64)],
+        WatermarkPosition::BottomRight => vec![Point::new(size.width - margin - width / 2f64, size.height - margin - height / 2f64)],
+        WatermarkPosition::Center => vec![size.to_rect().center()],
+        WatermarkPosition::Tiled => {
+            // every other row is shifted by half a step, and the tiles overflow the edges so that no corner is left bare
+            let (step_x, step_y) = (width * 1.5, height * 3f64);
+            let mut centers = Vec::new();
+            for row in 0..=(size.height / step_y).ceil() as usize + 1 {
+                let shift = if row % 2 == 1 { step_x / 2f64 } else { 0f64 };
+                for column in 0..=(size.width / step_x).ceil() as usize + 1 {
+                    centers.push(Point::new(column as f64 * step_x - shift, row as f64 * step_y));
+                }
+            }
+            centers
+        }
+    };
+    with_save(rc, |rc| {
+        rc.clip(size.to_rect());
+        for center in centers {
+            with_save(rc, |rc| {
+                rc.transform(Affine::translate(center.to_vec2()) * Affine::rotate(mark.rotation.to_radians()) * Affine::scale(scale) * Affine::translate(-content.to_vec2() / 2f64));
+                if let Some(image) = &image {
+                    rc.draw_image(image, content.to_rect(), InterpolationMode::Bilinear);
+                } else if let Some(layout) = layout.as_ref().and_then(|layout| layout.layout()) {
+                    rc.draw_text(layout, Point::ZERO);
+                }
+            });
+        }
+    });
+}
+
+/// Paints the image with its adjustments, stretched over a canvas of the given size.
+fn paint_image(rc: &mut Piet, data: &AppState, size: Size) {
+    // the format follows the pixels rather than the file, as margins and borders may add transparency
+    let image = data.shown_image();
+    paint_pixels(rc, data, size, image.width(), image.height(), image.raw_pixels(), image.format());
+}
+
+/// Paints pixels stretched over a canvas of the given size, flipped the way the image is.
+fn paint_pixels(rc: &mut Piet, data: &AppState, size: Size, pixel_width: usize, pixel_height: usize, pixels: &[u8], format: ImageFormat) {
+    let (width, height) = (size.width, size.height);
+    with_save(rc, |rc| {
+        rc.transform(transform(&data.affine, size));
+        let image = rc.make_image(pixel_width, pixel_height, pixels, format).unwrap();
+        rc.draw_image(&image, Rect::new(0f64, 0f64, width, height), InterpolationMode::Bilinear);
+    });
+}
+
+/// The outline of a spotlight area, running the opposite way to the outlines of rectangles and ellipses.
+fn spotlight_hole(start: Point, end: Point, round: bool) -> BezPath {
+    let area = Rect::from_points(start, end);
+    let center = area.center().to_vec2();
+    Affine::translate(center) * Affine::FLIP_X * Affine::translate(-center) * magnifier_outline(area, round)
+}
+
+/// The areas of a spotlight drawn with the transforms `from`, in the coordinates of one drawn with `to`,
+/// so they stay where they are shown when the image was flipped in between.
+fn moved_areas(areas: &[(Point, Point, bool)], from: &[Affine], to: &[Affine], size: Size) -> Vec<(Point, Point, bool)> {
+    let map = transform(to, size).inverse() * transform(from, size);
+    areas.iter().map(|(start_point, end_point, round)| (map * *start_point, map * *end_point, *round)).collect()
+}
+
+/// Paints the annotations over the image, in the coordinates of a canvas of the given size,
+/// but for the glyphs of the text annotation at `editing`, which the editor shows instead.
+fn paint_actions(rc: &mut Piet, data: &AppState, size: Size, editing: Option<usize>, env: &Env) {
+    let (width, height) = (size.width, size.height);
+    // numbers and letters are counted separately, so undoing a step renumbers the following ones
+    let mut steps = [0usize; 2];
+    let spotlight = data.spotlight().map(|(index, _)| index);
+    for (index, action) in data.visible_actions() {
+        match action {
+            Action::Highlighter(affine, action, color, stroke, tip) => {
+                with_save(rc, |rc| {
+                    rc.transform(transform(&data.affine, size) * transform(affine, size));
+                    let color = color.with_alpha(0.25);
+                    // piet has no multiply blending: the whole stroke is painted in a single
+                    // operation instead, so overlaps and joints do not darken
+                    match tip {
+                        HighlighterTip::Round if action.len() < 2 => {
+                            rc.fill(Circle::new(*action.last().unwrap(), stroke * 2f64), &color);
+                        }
+                        HighlighterTip::Round => {
+                            let mut path = BezPath::new();
+                            path.move_to(action[0]);
+                            for point in &action[1..] {
+                                path.line_to(*point);
                             }
+                            let style = StrokeStyle::new().line_cap(LineCap::Round).line_join(LineJoin::Round);
+                            rc.stroke_styled(&path, &color, stroke * 3f64, &style);
+                        }
+                        HighlighterTip::Chisel if action.len() < 2 => {
+                            let nib = Vec2::new(1f64, -1f64).normalize() * *stroke * 1.5;
+                            rc.stroke(Line::new(action[0] - nib, action[0] + nib), &color, *stroke);
+                        }
+                        HighlighterTip::Chisel => {
+                            rc.fill(chisel_path(action, stroke * 3f64), &color);
                         }
                     }
+                });
+            }
+            Action::Pen(affine, action, color, stroke, line_style, smooth, widths) => {
+                if action.len() < 2 {
+                    with_save(rc, |rc| {
+                        rc.transform(transform(&data.affine, size) * transform(affine, size));
+                        rc.fill(Circle::new(*action.last().unwrap(), stroke / 2f64), color);
+                    });
+                }
+                if action.len() >= 2 {
+                    with_save(rc, |rc| {
+                        rc.transform(transform(&data.affine, size) * transform(affine, size));
+                        // a single path, so dashes run along the whole stroke
+                        let path = if *smooth {
+                            smooth_path(action)
+                        } else {
+                            let mut path = BezPath::new();
+                            path.move_to(action[0]);
+                            for point in &action[1..] {
+                                path.line_to(*point);
+                            }
+                            path
+                        };
+                        if widths.len() == action.len() {
+                            // each segment gets its own width, their round caps hiding the joints
+                            let style = StrokeStyle::new().line_cap(LineCap::Round);
+                            for (i, segment) in path.segments().enumerate() {
+                                rc.stroke_styled(segment, color, (widths[i] + widths[i + 1]) / 2f64, &style);
+                            }
+                        } else {
+                            rc.stroke_styled(&path, color, *stroke, &line_style.stroke_style(*stroke));
+                        }
+                    });
                 }
             }
-            Event::Paste(clipboard) => {
-                if data.is_writing_text {
-                    if let Some(action) = data.actions.last_mut() {
-                        if let Action::Text(affine, _, string, _, _) = action {
-                            *affine = data.affine.clone();
-                            *string = clipboard.get_string().unwrap();
+            Action::Rectangle(affine, start_point, end_point, color, fill, stroke, line_style) => {
+                with_save(rc, |rc| {
+                    rc.transform(transform(&data.affine, size) * transform(affine, size));
+                    if let Some(fill) = fill {
+                        rc.fill_even_odd(Rect::new(start_point.x, start_point.y, end_point.x, end_point.y), fill);
+                    }
+                    rc.stroke_styled(Rect::new(start_point.x, start_point.y, end_point.x, end_point.y), color, *stroke, &line_style.stroke_style(*stroke));
+                });
+            }
+            Action::Circle(affine, center, radius, color, fill, stroke, line_style) => {
+                with_save(rc, |rc| {
+                    rc.transform(transform(&data.affine, size) * transform(affine, size));
+                    if let Some(fill) = fill {
+                        rc.fill_even_odd(Circle::new(*center, *radius), fill);
+                    }
+                    rc.stroke_styled(Circle::new(*center, *radius), color, *stroke, &line_style.stroke_style(*stroke));
+                });
+            }
+            Action::Ellipse(affine, start_point, end_point, color, fill, stroke, line_style) => {
+                with_save(rc, |rc| {
+                    rc.transform(transform(&data.affine, size) * transform(affine, size));
+                    if let Some(fill) = fill {
+                        rc.fill_even_odd(Ellipse::from_rect(Rect::new(start_point.x, start_point.y, end_point.x, end_point.y)), fill);
+                    }
+                    rc.stroke_styled(Ellipse::from_rect(Rect::new(start_point.x, start_point.y, end_point.x, end_point.y)), color, *stroke, &line_style.stroke_style(*stroke));
+                });
+            }
+            Action::Arrow(affine, start_point, end_point, color, stroke, line_style, style, control) => {
+                with_save(rc, |rc| {
+                    rc.transform(transform(&data.affine, size) * transform(affine, size));
+                    // Draw the line, straight arrows being curves with the control point in the middle
+                    let control = control.unwrap_or_else(|| start_point.midpoint(*end_point));
+                    let curve = QuadBez::new(*start_point, control, *end_point);
+                    let len = curve.arclen(0.1);
+                    if len < 1f64 { return; }
+                    // stop the line inside filled heads, so it does not show around their tips
+                    let trim = |headed: bool| if headed && style.head == ArrowHead::Filled { (arrowhead_length(*stroke) / 2f64 / len).min(0.5) } else { 0f64 };
+                    rc.stroke_styled(curve.subsegment(trim(style.tail == ArrowTail::Head)..1f64 - trim(true)), color, *stroke, &line_style.stroke_style(*stroke));
+                    // Draw the arrowhead, along the direction of the curve at its end
+                    draw_arrowhead(rc, control, *end_point, color, *stroke, style.head);
+                    match style.tail {
+                        ArrowTail::None => {}
+                        ArrowTail::Head => draw_arrowhead(rc, control, *start_point, color, *stroke, style.head),
+                        ArrowTail::Dot => rc.fill(Circle::new(*start_point, arrowhead_length(*stroke) / 3f64), color),
+                        ArrowTail::Bar => {
+                            let (left_point, right_point) = calculate_arrowhead(control, *start_point, 0f64, arrowhead_length(*stroke));
+                            rc.stroke(Line::new(left_point, right_point), color, *stroke);
                         }
                     }
+                });
+            }
+            Action::RoundedRectangle(affine, start_point, end_point, color, fill, stroke, line_style, radius) => {
+                with_save(rc, |rc| {
+                    rc.transform(transform(&data.affine, size) * transform(affine, size));
+                    let rect = Rect::from_points(*start_point, *end_point).to_rounded_rect(*radius);
+                    if let Some(fill) = fill {
+                        rc.fill_even_odd(rect, fill);
+                    }
+                    rc.stroke_styled(rect, color, *stroke, &line_style.stroke_style(*stroke));
+                });
+            }
+            Action::Polygon(affine, start_point, end_point, color, fill, stroke, line_style, shape) => {
+                with_save(rc, |rc| {
+                    rc.transform(transform(&data.affine, size) * transform(affine, size));
+                    let path = shape_path(*shape, *start_point, *end_point);
+                    if let Some(fill) = fill {
+                        rc.fill_even_odd(&path, fill);
+                    }
+                    rc.stroke_styled(&path, color, *stroke, &line_style.stroke_style(*stroke));
+                });
+            }
+            Action::Line(affine, start_point, end_point, color, stroke, line_style) => {
+                with_save(rc, |rc| {
+                    rc.transform(transform(&data.affine, size) * transform(affine, size));
+                    rc.stroke_styled(Line::new(*start_point, *end_point), color, *stroke, &line_style.stroke_style(*stroke));
+                });
+            }
+            Action::Polyline(affine, points, color, closed, fill, stroke, line_style) => {
+                with_save(rc, |rc| {
+                    rc.transform(transform(&data.affine, size) * transform(affine, size));
+                    let mut path = BezPath::new();
+                    path.move_to(points[0]);
+                    for point in &points[1..] {
+                        path.line_to(*point);
+                    }
+                    if *closed {
+                        path.close_path();
+                    }
+                    if let (Some(fill), true) = (fill, closed) {
+                        rc.fill_even_odd(&path, fill);
+                    }
+                    rc.stroke_styled(&path, color, *stroke, &line_style.stroke_style(*stroke));
+                });
+            }
+            Action::Text(affine, pos, text, color, font_size, style) => {
+                with_save(rc, |rc| {
+                    rc.transform(transform(&data.affine, size) * transform(affine, size));
+                    let mut layout = text_layout(text, *color, *font_size, style);
+                    layout.rebuild_if_needed(rc.text(), env);
+                    if let Some(background) = style.background {
+                        rc.fill(text_bounds(&layout, style, *pos).to_rounded_rect(style.radius), &background);
+                    }
+                    // the text being written is shown by the editor instead
+                    if editing == Some(index) { return; }
+                    draw_outline(rc, text, *font_size, style, *pos, env);
+                    if let Some(glyphs) = layout.layout() { rc.draw_text(glyphs, *pos); }
+                });
+            }
+            Action::Step(affine, center, color, font_size, style) => {
+                let sequence = (*style == StepStyle::Letters) as usize;
+                let label = style.label(steps[sequence]);
+                steps[sequence] += 1;
+                with_save(rc, |rc| {
+                    rc.transform(transform(&data.affine, size) * transform(affine, size));
+                    let radius = font_size * 0.75;
+                    if *style == StepStyle::Square {
+                        rc.fill(Rect::from_center_size(*center, (radius * 2f64, radius * 2f64)).to_rounded_rect(radius / 4f64), color);
+                    } else {
+                        rc.fill(Circle::new(*center, radius), color);
+                    }
+                    // keep the label readable on light markers
+                    let (r, g, b, _) = color.as_rgba();
+                    let label_color = if 0.299 * r + 0.587 * g + 0.114 * b > 0.6 { Color::BLACK } else { Color::WHITE };
+                    let mut layout = text_layout(&label, label_color, *font_size, &TextStyle::default());
+                    layout.rebuild_if_needed(rc.text(), env);
+                    let origin = *center - layout.size().to_vec2() / 2f64;
+                    if let Some(glyphs) = layout.layout() { rc.draw_text(glyphs, origin); }
+                });
+            }
+            Action::Callout(affine, pos, text, color, font_size, style, tail) => {
+                with_save(rc, |rc| {
+                    rc.transform(transform(&data.affine, size) * transform(affine, size));
+                    let mut layout = text_layout(text, *color, *font_size, style);
+                    layout.rebuild_if_needed(rc.text(), env);
+                    let bounds = text_bounds(&layout, style, *pos);
+                    let bubble = bounds.to_rounded_rect(style.radius);
+                    let pointer = callout_tail(bounds, *tail);
+                    let background = style.background.unwrap_or(Color::WHITE);
+                    // the inner half of the border is covered by the fill, so the box and its tail read as one shape
+                    let border = font_size / 6f64;
+                    rc.stroke(bubble, color, border);
+                    rc.stroke(&pointer, color, border);
+                    rc.fill(bubble, &background);
+                    rc.fill(&pointer, &background);
+                    if editing == Some(index) { return; }
+                    draw_outline(rc, text, *font_size, style, *pos, env);
+                    if let Some(glyphs) = layout.layout() { rc.draw_text(glyphs, *pos); }
+                });
+            }
+            Action::Magnify(affine, start_point, end_point, inset, color, stroke, style) => {
+                with_save(rc, |rc| {
+                    let local = transform(&data.affine, size) * transform(affine, size);
+                    rc.transform(local);
+                    let source = Rect::from_points(*start_point, *end_point);
+                    if let Some(inset) = inset {
+                        let zoomed = magnifier_inset(*start_point, *end_point, *inset, style.zoom);
+                        if style.connector {
+                            rc.stroke(Line::new(edge_point(source, zoomed.center(), style.round), edge_point(zoomed, source.center(), style.round)), color, *stroke);
+                        }
+                        with_save(rc, |rc| {
+                            rc.clip(magnifier_outline(zoomed, style.round));
+                            // the image as shown on the canvas, with the source area mapped onto the inset
+                            let enlarge = Affine::translate(zoomed.origin().to_vec2()) * Affine::scale(style.zoom) * Affine::translate(-source.origin().to_vec2());
+                            rc.transform(enlarge * local.inverse());
+                            paint_image(rc, data, size);
+                        });
+                        rc.stroke(magnifier_outline(zoomed, style.round), color, *stroke);
+                    }
+                    rc.stroke(magnifier_outline(source, style.round), color, *stroke);
+                });
+            }
+            Action::Spotlight(affine, areas, dim, blur) => {
+                if spotlight != Some(index) || areas.is_empty() {
+                    continue;
                 }
+                with_save(rc, |rc| {
+                    let local = transform(&data.affine, size) * transform(affine, size);
+                    // each clip keeps what is outside one area, as the hole winds the other way round,
+                    // and clips add up to what is outside all of them
+                    for (start_point, end_point, round) in areas {
+                        let mut outside = local.inverse() * Rect::new(0f64, 0f64, width, height).into_path(0.1);
+                        outside.extend(spotlight_hole(*start_point, *end_point, *round));
+                        rc.clip(local * outside);
+                    }
+                    if *blur > 0f64 {
+                        // blurred from the image as it is shown, so that it is adjusted like the rest of it
+                        let blurred = data.blurred_image(*blur);
+                        paint_pixels(rc, data, size, blurred.width(), blurred.height(), blurred.raw_pixels(), blurred.format());
+                    }
+                    rc.fill(Rect::new(0f64, 0f64, width, height), &Color::BLACK.with_alpha(*dim));
+                });
+            }
+            Action::Stamp(affine, center, stamp_width, image) => {
+                with_save(rc, |rc| {
+                    rc.transform(transform(&data.affine, size) * transform(affine, size));
+                    if let Ok(stamp) = rc.make_image(image.width() as usize, image.height() as usize, image.as_bytes(), ImageFormat::RgbaSeparate) {
+                        rc.draw_image(&stamp, stamp_rect(*center, *stamp_width, image), InterpolationMode::Bilinear);
+                    }
+                });
+            }
+            Action::Erase(..) | Action::Trim(..) => {}
+            Action::Crop(_, start_point, end_point) => {
+                if data.crop.get() {
+                    let background_color = Color::rgba(1.0, 1.0, 1.0, 0.05);
+                    rc.fill(Rect::from_points(*start_point, *end_point), &background_color);
+
+                    // Set the border color
+                    let border_color = Color::GRAY;
+
+                    // Draw the border
+                    let border_width = 1.0;
+                    let border_rect = Rect::from_points(*start_point, *end_point).inset(-border_width / 2.0);
+                    let stroke_style = StrokeStyle::new().dash_pattern(&[2.0]);
+                    rc.stroke_styled(border_rect, &border_color, border_width, &stroke_style);
+                }
+            }
+        }
+    }
+}
+
+/// The theme used by the text editor, so that it blends with the annotation being written.
+fn text_box_env(env: &Env, color: Color) -> Env {
+    let mut env = env.clone();
+    env.set(theme::BACKGROUND_LIGHT, Color::TRANSPARENT);
+    env.set(theme::BORDER_DARK, Color::TRANSPARENT);
+    env.set(theme::PRIMARY_LIGHT, Color::TRANSPARENT);
+    env.set(theme::TEXTBOX_BORDER_WIDTH, 0f64);
+    env.set(theme::TEXTBOX_INSETS, Insets::ZERO);
+    env.set(theme::CURSOR_COLOR, color);
+    env
+}
+
+pub struct DrawingWidget {
+    text_box: WidgetPod<String, TextBox<String>>,
+    /// Number of actions before the last click, so a double-click can drop what its first click drew.
+    actions_before_click: usize,
+    /// Index of the annotation whose handle is being dragged, and which handle.
+    dragging: Option<(usize, Handle)>,
+    /// Where the shape being drawn was started, and how far it was nudged with the arrow keys.
+    anchor: Point,
+    nudge: Vec2,
+}
+
+impl DrawingWidget {
+    pub fn new() -> Self {
+        let mut text_box = TextBox::multiline().with_line_wrapping(true);
+        text_box.text_mut().borrow_mut().send_notification_on_return = true;
+        DrawingWidget { text_box: WidgetPod::new(text_box), actions_before_click: 0, dragging: None, anchor: Point::ZERO, nudge: Vec2::ZERO }
+    }
+}
+
+impl Widget<AppState> for DrawingWidget {
+    fn event(&mut self, ctx: &mut druid::EventCtx, event: &Event, data: &mut AppState, env: &Env) {
+        if let Event::Command(command) = event {
+            let window = if command.is(dialogs::SHOW_IMAGE_SIZE) {
+                Some(dialogs::image_size_window(data))
+            } else if command.is(dialogs::SHOW_CANVAS_SIZE) {
+                Some(dialogs::canvas_size_window())
+            } else if command.is(dialogs::SHOW_BORDER) {
+                Some(dialogs::border_window())
+            } else if command.is(dialogs::SHOW_ADJUSTMENTS) {
+                Some(dialogs::adjustments_window())
+            } else if command.is(dialogs::SHOW_WATERMARK) {
+                Some(dialogs::watermark_window())
+            } else if command.is(dialogs::SHOW_DEVICE_FRAME) {
+                Some(dialogs::device_frame_window())
+            } else if command.is(dialogs::SHOW_FRAME) {
+                Some(dialogs::frame_window())
+            } else {
+                None
+            };
+            if let Some(window) = window {
+                finish_text(data);
+                ctx.new_window(window);
+                ctx.set_handled();
+                return;
+            }
+        }
+        if let Event::MouseDown(e) = event {
+            if e.count == 2 && !data.is_picking_color && !data.is_drawing_polyline && data.selection != utils::Selection::Crop {
+                if let Some((index, caret)) = hit_text(ctx, data, e.pos, env).filter(|(index, _)| *index < self.actions_before_click && Some(*index) != data.writing_text) {
+                    finish_text(data);
+                    data.actions.truncate(self.actions_before_click);
+                    let size = ctx.size();
+                    let global = data.affine.clone();
+                    if let Some(Action::Callout(affine, _, _, _, _, _, tail)) = data.actions.get_mut(index) {
+                        *tail = transform(&global, size) * transform(affine, size) * *tail;
+                    }
+                    if let Some(Action::Text(affine, position, text, color, font_size, style) | Action::Callout(affine, position, text, color, font_size, style, _)) = data.actions.get_mut(index) {
+                        // bring the annotation back to screen space, where the editor lives
+                        let mut layout = text_layout(text, *color, *font_size, style);
+                        layout.rebuild_if_needed(ctx.text(), env);
+                        let bounds = (transform(&global, size) * transform(affine, size)).transform_rect_bbox(text_bounds(&layout, style, *position));
+                        *position = bounds.origin() + (style.padding, style.padding);
+                        *affine = global;
+                    }
+                    data.writing_text = Some(index);
+                    data.repaint = true;
+                    if let Some(invalidation) = self.text_box.widget_mut().text_mut().borrow_mut().set_selection(Selection::caret(caret)) {
+                        ctx.invalidate_text_input(invalidation);
+                    }
+                    ctx.set_focus(self.text_box.id());
+                    ctx.request_layout();
+                    ctx.request_paint();
+                    return;
+                }
+            }
+            self.actions_before_click = data.actions.len();
+        }
+        if data.writing_text.is_some() && !data.is_picking_color {
+            match event {
+                Event::MouseDown(e) if !self.text_box.layout_rect().contains(e.pos) => {
+                    finish_text(data);
+                    self.actions_before_click = data.actions.len();
+                    ctx.request_focus();
+                    ctx.request_paint();
+                    return;
+                }
+                Event::MouseMove(e) if data.is_drawing => {
+                    // dragging while placing the text sets its wrap width
+                    if let Some(Action::Text(_, position, _, _, _, style)) = data.writing_text.and_then(|index| data.actions.get_mut(index)) {
+                        let width = e.pos.x - position.x;
+                        style.width = if width > 10f64 { Some(width) } else { None };
+                    }
+                    ctx.request_layout();
+                    ctx.request_paint();
+                    return;
+                }
+                Event::MouseUp(_) => {
+                    data.is_drawing = false;
+                }
+                Event::KeyDown(key) if key.code.eq(&Code::Escape) => {
+                    finish_text(data);
+                    ctx.request_focus();
+                    ctx.set_handled();
+                    return;
+                }
+                Event::Notification(cmd) if cmd.is(TextComponent::RETURN) => {
+                    finish_text(data);
+                    ctx.request_focus();
+                    ctx.set_handled();
+                    return;
+                }
+                _ => {}
+            }
+            let mut text = editing_text(data);
+            if let Event::KeyDown(key) = event {
+                // Enter commits the text, Shift+Enter starts a new line
+                if key.key == KbKey::Enter && key.mods.shift() && !self.text_box.widget().text().is_composing() {
+                    let invalidation = self.text_box.widget_mut().text_mut().borrow_mut().insert_text(&mut text, "\n");
+                    ctx.invalidate_text_input(invalidation);
+                    ctx.set_handled();
+                }
+            }
+            if !ctx.is_handled() {
+                self.text_box.event(ctx, event, &mut text, &text_box_env(env, data.color));
+            }
+            if let Some(Action::Text(affine, _, string, _, _, _) | Action::Callout(affine, _, string, _, _, _, _)) = data.writing_text.and_then(|index| data.actions.get_mut(index)) {
+                if !text.eq(string) {
+                    *affine = data.affine.clone();
+                    *string = text;
+                }
+            }
+            return;
+        }
+        // Handle user input events for drawing here
+        match event {
+            Event::KeyDown(key) if data.is_drawing_polyline && (key.code.eq(&Code::Enter) || key.code.eq(&Code::Escape)) => {
+                finish_polyline(data);
+                ctx.request_paint();
+            }
+            Event::KeyDown(key) if data.is_drawing && matches!(key.key, KbKey::ArrowLeft | KbKey::ArrowRight | KbKey::ArrowUp | KbKey::ArrowDown) => {
+                // arrow keys nudge the shape being drawn, by 10 points with Shift
+                let step = if key.mods.shift() { 10f64 } else { 1f64 };
+                let delta = match key.key {
+                    KbKey::ArrowLeft => Vec2::new(-step, 0f64),
+                    KbKey::ArrowRight => Vec2::new(step, 0f64),
+                    KbKey::ArrowUp => Vec2::new(0f64, -step),
+                    _ => Vec2::new(0f64, step),
+                };
+                self.anchor += delta;
+                self.nudge += delta;
+                if let Some(action) = data.actions.last_mut() {
+                    nudge_shape(action, delta);
+                }
+                ctx.set_handled();
+                ctx.request_paint();
             }
             Event::MouseDown(e) => {
                 if data.is_picking_color {
                     ctx.set_cursor(&Cursor::Pointer);
                     return;
                 }
+                if data.is_drawing_polyline {
+                    if let Some(Action::Polyline(_, points, _, closed, _, _, _)) = data.actions.last_mut() {
+                        let first = points[0];
+                        if e.count >= 2 {
+                            finish_polyline(data);
+                        } else if *closed && points.len() > 3 && first.distance(e.pos) < 10f64 {
+                            // clicking the first vertex finishes the polygon
+                            finish_polyline(data);
+                        } else {
+                            let vertex = *points.last().unwrap();
+                            points.push(vertex);
+                        }
+                    }
+                    ctx.request_paint();
+                    return;
+                }
+                if let Some(handle) = hit_handle(data, e.pos, ctx.size()) {
+                    self.dragging = Some(handle);
+                    self.anchor = e.pos;
+                    ctx.set_active(true);
+                    return;
+                }
                 data.is_drawing = true;
+                self.anchor = e.pos;
+                self.nudge = Vec2::ZERO;
+                ctx.request_focus();
                 let mut action = Action::new(&data.selection);
                 ctx.set_cursor(&Cursor::Crosshair);
                 match action {
-                    Action::Pen(ref mut affine, ref mut points, ref mut color, ref mut stroke) => {
+                    Action::Pen(ref mut affine, ref mut points, ref mut color, ref mut stroke, ref mut line_style, ref mut smooth, ref mut widths) => {
                         points.push(e.pos);
+                        *smooth = data.pen_smoothing;
+                        if data.pen_velocity {
+                            widths.push(data.stroke);
+                        }
                         *color = data.color;
                         *stroke = data.stroke;
+                        *line_style = data.line_style;
                         *affine = data.affine.clone();
                     }
-                    Action::Highlighter(ref mut affine, ref mut points, ref mut color, ref mut stroke) => {
+                    Action::Highlighter(ref mut affine, ref mut points, ref mut color, ref mut stroke, ref mut tip) => {
                         points.push(e.pos);
+                        *tip = data.highlighter_tip;
                         *color = data.color;
                         *stroke = data.stroke;
                         *affine = data.affine.clone();
                     }
-                    Action::Rectangle(ref mut affine, ref mut start_point, ref mut end_point, ref mut color, ref mut fill, ref mut stroke) => {
+                    Action::Rectangle(ref mut affine, ref mut start_point, ref mut end_point, ref mut color, ref mut fill, ref mut stroke, ref mut line_style) => {
                         *start_point = e.pos;
                         *end_point = e.pos;
-                        *fill = data.fill_color;
+                        *fill = data.shape_fill();
                         *color = data.color;
                         *stroke = data.stroke;
+                        *line_style = data.line_style;
                         *affine = data.affine.clone();
                     }
-                    Action::Circle(ref mut affine, ref mut center, _, ref mut color, ref mut fill, ref mut stroke) => {
+                    Action::Circle(ref mut affine, ref mut center, _, ref mut color, ref mut fill, ref mut stroke, ref mut line_style) => {
                         *center = e.pos;
-                        *fill = data.fill_color;
+                        *fill = data.shape_fill();
+                        *color = data.color;
+                        *stroke = data.stroke;
+                        *line_style = data.line_style;
+                        *affine = data.affine.clone();
+                    }
+                    Action::RoundedRectangle(ref mut affine, ref mut start_point, ref mut end_point, ref mut color, ref mut fill, ref mut stroke, ref mut line_style, ref mut radius) => {
+                        *start_point = e.pos;
+                        *end_point = e.pos;
+                        *fill = data.shape_fill();
+                        *color = data.color;
+                        *stroke = data.stroke;
+                        *line_style = data.line_style;
+                        *affine = data.affine.clone();
+                        *radius = data.corner_radius;
+                    }
+                    Action::Polygon(ref mut affine, ref mut start_point, ref mut end_point, ref mut color, ref mut fill, ref mut stroke, ref mut line_style, ref mut shape) => {
+                        *start_point = e.pos;
+                        *end_point = e.pos;
+                        *fill = data.shape_fill();
                         *color = data.color;
                         *stroke = data.stroke;
+                        *line_style = data.line_style;
                         *affine = data.affine.clone();
+                        if let Shape::Regular(sides) = shape {
+                            *sides = data.polygon_sides;
+                        }
                     }
-                    Action::Ellipse(ref mut affine, ref mut start_point, ref mut end_point, ref mut color, ref mut fill, ref mut stroke) => {
+                    Action::Ellipse(ref mut affine, ref mut start_point, ref mut end_point, ref mut color, ref mut fill, ref mut stroke, ref mut line_style) => {
                         *start_point = e.pos;
                         *end_point = e.pos;
-                        *fill = data.fill_color;
+                        *fill = data.shape_fill();
+                        *color = data.color;
+                        *stroke = data.stroke;
+                        *line_style = data.line_style;
+                        *affine = data.affine.clone();
+                    }
+                    Action::Line(ref mut affine, ref mut start_point, ref mut end_point, ref mut color, ref mut stroke, ref mut line_style) => {
+                        *start_point = e.pos;
+                        *end_point = e.pos;
+                        *color = data.color;
+                        *stroke = data.stroke;
+                        *line_style = data.line_style;
+                        *affine = data.affine.clone();
+                    }
+                    Action::Polyline(ref mut affine, ref mut points, ref mut color, ref mut closed, ref mut fill, ref mut stroke, ref mut line_style) => {
+                        // the last vertex follows the mouse until the next click
+                        points.push(e.pos);
+                        points.push(e.pos);
+                        *closed = data.close_polyline;
+                        *fill = data.shape_fill();
                         *color = data.color;
                         *stroke = data.stroke;
+                        *line_style = data.line_style;
                         *affine = data.affine.clone();
+                        data.is_drawing_polyline = true;
                     }
-                    Action::Arrow(ref mut affine, ref mut start_point, ref mut end_point, ref mut color, ref mut stroke) => {
+                    Action::Arrow(ref mut affine, ref mut start_point, ref mut end_point, ref mut color, ref mut stroke, ref mut line_style, ref mut style, ref mut control) => {
                         *start_point = e.pos;
                         *end_point = e.pos;
                         *color = data.color;
                         *stroke = data.stroke;
+                        *line_style = data.line_style;
                         *affine = data.affine.clone();
+                        *style = data.arrow_style;
+                        // curved arrows start straight, then their middle can be dragged
+                        *control = if style.curved { Some(e.pos) } else { None };
                     }
-                    Action::Text(ref mut affine, ref mut position, _, ref mut color, ref mut font_size) => {
-                        if data.is_writing_text { return; }
-                        ctx.request_focus();
+                    Action::Text(ref mut affine, ref mut position, _, ref mut color, ref mut font_size, ref mut style) => {
+                        ctx.set_focus(self.text_box.id());
                         *position = e.pos;
                         *color = data.color;
                         *affine = data.affine.clone();
                         *font_size = data.font_size;
+                        *style = data.text_style.clone();
                         // Set a flag or state indicating that text input is needed
-                        data.is_writing_text = true;
+                        data.writing_text = Some(data.actions.len());
+                    }
+                    Action::Step(ref mut affine, ref mut center, ref mut color, ref mut font_size, ref mut style) => {
+                        *center = e.pos;
+                        *color = data.color;
+                        *affine = data.affine.clone();
+                        *font_size = data.font_size;
+                        *style = data.step_style;
                     }
+                    Action::Callout(ref mut affine, ref mut position, _, ref mut color, ref mut font_size, ref mut style, ref mut tail) => {
+                        *position = e.pos;
+                        *tail = e.pos;
+                        *color = data.color;
+                        *affine = data.affine.clone();
+                        *font_size = data.font_size;
+                        *style = data.text_style.clone();
+                    }
+                    Action::Magnify(ref mut affine, ref mut start_point, ref mut end_point, _, ref mut color, ref mut stroke, ref mut style) => {
+                        *start_point = e.pos;
+                        *end_point = e.pos;
+                        *color = data.color;
+                        *stroke = data.stroke;
+                        *affine = data.affine.clone();
+                        *style = data.magnifier_style;
+                    }
+                    Action::Spotlight(ref mut affine, ref mut areas, ref mut dim, ref mut blur) => {
+                        // the new area joins those of the current spotlight, so undo takes away just this one
+                        if let Some((_, Action::Spotlight(prev_affine, prev_areas, _, _))) = data.spotlight() {
+                            *areas = moved_areas(prev_areas, prev_affine, &data.affine, ctx.size());
+                        }
+                        areas.push((e.pos, e.pos, data.spotlight_round));
+                        *dim = data.spotlight_dim;
+                        *blur = data.spotlight_blur;
+                        *affine = data.affine.clone();
+                    }
+                    Action::Stamp(ref mut affine, ref mut center, ref mut width, ref mut image) => {
+                        *center = e.pos;
+                        *width = data.stamp_size;
+                        if let Some(stamp) = data.stamps.get(data.stamp) {
+                            *image = stamp.image.clone();
+                        }
+                        *affine = data.affine.clone();
+                    }
+                    Action::Erase(..) | Action::Trim(..) => {}
                     Action::Crop(ref mut prev_image, ref mut start_point, ref mut end_point) => {
 
                         let x = ctx.window().get_position().x.floor();
@@ -148,7 +1394,10 @@ impl Widget<AppState> for DrawingWidget {
                         *end_point = e.pos;
                     }
                 }
-                data.actions.push(action);
+                data.push_action(action);
+                if data.selection == utils::Selection::Eraser {
+                    erase(ctx, data, e.pos, env);
+                }
                 ctx.request_paint();
             }
             Event::MouseMove(e) => {
@@ -157,27 +1406,84 @@ impl Widget<AppState> for DrawingWidget {
                     return;
                 }
                 ctx.set_cursor(&Cursor::Crosshair);
+                if let Some((index, handle)) = self.dragging {
+                    let size = ctx.size();
+                    let global = transform(&data.affine, size);
+                    match data.actions.get_mut(index) {
+                        Some(Action::Magnify(affine, start_point, end_point, Some(inset), _, _, _)) => {
+                            let local = (global * transform(affine, size)).inverse();
+                            let delta = local * e.pos - local * self.anchor;
+                            if handle == Handle::Inset {
+                                *inset += delta;
+                            } else {
+                                // the inset follows what is under the source area as it moves
+                                *start_point += delta;
+                                *end_point += delta;
+                            }
+                            self.anchor = e.pos;
+                        }
+                        Some(Action::Stamp(affine, center, width, image)) => {
+                            let local = (global * transform(affine, size)).inverse();
+                            if handle == Handle::Corner {
+                                *width = stamp_width(*center, local * e.pos, image);
+                            } else {
+                                *center += local * e.pos - local * self.anchor;
+                            }
+                            self.anchor = e.pos;
+                        }
+                        Some(Action::Callout(affine, _, _, _, _, _, tail)) => {
+                            *tail = (global * transform(affine, size)).inverse() * e.pos;
+                        }
+                        Some(Action::Arrow(affine, start_point, end_point, _, _, _, _, Some(control))) => {
+                            // move the control point so that the middle of the curve follows the mouse
+                            let handle = (global * transform(affine, size)).inverse() * e.pos;
+                            *control = (handle.to_vec2() * 2f64 - (start_point.to_vec2() + end_point.to_vec2()) / 2f64).to_point();
+                        }
+                        _ => {}
+                    }
+                    ctx.request_paint();
+                    return;
+                }
+                if data.is_drawing_polyline {
+                    if let Some(Action::Polyline(_, points, _, _, _, _, _)) = data.actions.last_mut() {
+                        let count = points.len();
+                        points[count - 1] = if e.mods.shift() { snap_angle(points[count - 2], e.pos) } else { e.pos };
+                    }
+                    ctx.request_paint();
+                }
+                if data.is_drawing && data.selection == utils::Selection::Eraser {
+                    // erase all along the way from the last position, so quick moves do not skip anything
+                    let steps = (self.anchor.distance(e.pos) / (data.stroke * 2f64)).ceil().max(1f64) as usize;
+                    for step in 1..=steps {
+                        erase(ctx, data, self.anchor.lerp(e.pos, step as f64 / steps as f64), env);
+                    }
+                    self.anchor = e.pos;
+                    ctx.request_paint();
+                    return;
+                }
                 if data.is_drawing {
+                    let straight_highlight = data.straight_highlight;
                     if let Some(action) = data.actions.last_mut() {
                         match action {
-                            Action::Pen(_, points, _, _) => { points.push(e.pos); }
-                            Action::Highlighter(_, points, _, _) => { points.push(e.pos); }
-                            Action::Rectangle(_, _, end_point, _, _, _) => {
-                                *end_point = e.pos;
-                            }
-                            Action::Circle(_, center, radius, _, _, _) => {
-                                *radius = f64::sqrt(num_traits::pow(center.x - e.pos.x, 2) + num_traits::pow(center.y - e.pos.y, 2));
+                            Action::Pen(_, points, _, stroke, _, _, widths) => {
+                                if let (Some(width), Some(last)) = (widths.last(), points.last()) {
+                                    widths.push(pen_width(*stroke, *width, *last, e.pos));
+                                }
+                                points.push(e.pos);
                             }
-                            Action::Ellipse(_, _, end_point, _, _, _) => {
-                                *end_point = e.pos;
+                            Action::Highlighter(_, points, _, _, _) if straight_highlight => {
+                                // a horizontal highlight along the line of text where it started
+                                points.truncate(1);
+                                points.push(Point::new(e.pos.x, points[0].y));
                             }
-                            Action::Arrow(_, _, end_point, _, _) => {
-                                *end_point = e.pos;
+                            Action::Highlighter(_, points, _, _, _) => { points.push(e.pos); }
+                            Action::Callout(_, position, _, _, _, _, _) => {
+                                *position = e.pos;
                             }
-                            Action::Crop(_, _, end_point) => {
-                                *end_point = e.pos;
+                            Action::Step(_, center, _, _, _) => {
+                                *center = e.pos;
                             }
-                            _ => {}
+                            _ => drag_shape(action, self.anchor, e.pos + self.nudge, e.mods),
                         }
                     }
                     ctx.request_paint();
@@ -185,40 +1491,86 @@ impl Widget<AppState> for DrawingWidget {
             }
             Event::MouseUp(e) => {
                 if data.is_picking_color {
-                    let img = image::open(data.image_path.to_string()).unwrap();
+                    let img = image::open(&data.image_path).unwrap();
                     let x = (img.width() * u32::from_f64(e.pos.x).unwrap()) / u32::from_f64(ctx.size().width).unwrap();
                     let y = (img.height() * u32::from_f64(e.pos.y).unwrap()) / u32::from_f64(ctx.size().height).unwrap();
                     let pixel = img.get_pixel(x, y);
-                    data.color = Color::rgba8(pixel.0[0], pixel.0[1], pixel.0[2], pixel.0[3]);
+                    data.set_color(Color::rgba8(pixel.0[0], pixel.0[1], pixel.0[2], pixel.0[3]));
                     ctx.set_cursor(&Cursor::Arrow);
                     data.custom_color = true;
                     data.is_picking_color = false;
                     return;
                 }
-                if let Some(Action::Rectangle(_, _, end_point, _, _, _)) = data.actions.last_mut() {
-                    *end_point = e.pos;
+                if self.dragging.take().is_some() {
+                    ctx.set_active(false);
+                    data.repaint = true;
+                    return;
                 }
-                if let Some(Action::Circle(_, center, radius, _, _, _)) = data.actions.last_mut() {
-                    *radius = f64::sqrt(num_traits::pow(center.x - e.pos.x, 2) + num_traits::pow(center.y - e.pos.y, 2));
+                if let Some(Action::Erase(erased, _)) = data.actions.last() {
+                    if erased.is_empty() {
+                        data.actions.pop();
+                    }
                 }
-                if let Some(Action::Ellipse(_, _, end_point, _, _, _)) = data.actions.last_mut() {
-                    *end_point = e.pos;
+                if data.is_drawing {
+                    if let Some(action) = data.actions.last_mut() {
+                        drag_shape(action, self.anchor, e.pos + self.nudge, e.mods);
+                    }
+                    // drop the points that do not change the shape of the stroke
+                    if let Some(Action::Pen(_, points, _, _, _, _, widths)) = data.actions.last_mut() {
+                        let kept = simplify(points, 0.5);
+                        if !widths.is_empty() {
+                            *widths = kept.iter().map(|i| widths[*i]).collect();
+                        }
+                        *points = kept.iter().map(|i| points[*i]).collect();
+                    }
+                    if data.smart_pen {
+                        if let Some(shape) = data.actions.last().and_then(|action| recognize_shape(action, data.arrow_style)) {
+                            // the stroke is replaced the way the eraser does it, so undo brings it back
+                            let index = data.actions.len() - 1;
+                            data.push_action(Action::Erase(vec![(index, vec![shape])], true));
+                        }
+                    }
                 }
-                if let Some(Action::Arrow(_, _, _, _, _)) = data.actions.last_mut() {}
-                if let Some(Action::Text(_, position, _, color, _)) = data.actions.last_mut() {
-                    if data.is_writing_text { return; }
-                    ctx.request_focus();
-                    *position = e.pos;
-                    *color = data.color;
-                    // Set a flag or state indicating that text input is needed
-                    data.is_writing_text = true;
+                let mut too_small = false;
+                if let Some(Action::Magnify(_, start_point, end_point, inset, _, _, style)) = data.actions.last_mut() {
+                    if data.is_drawing {
+                        let source = Rect::from_points(*start_point, *end_point);
+                        let zoomed = source.size() * style.zoom;
+                        let size = ctx.size();
+                        // the inset goes beside the area it enlarges, on the side with more room
+                        let x = if source.center().x < size.width / 2f64 { source.x1 + 20f64 + zoomed.width / 2f64 } else { source.x0 - 20f64 - zoomed.width / 2f64 };
+                        let y = source.center().y.min(size.height - zoomed.height / 2f64).max(zoomed.height / 2f64);
+                        *inset = Some(Point::new(x, y));
+                        too_small = source.width() < 4f64 || source.height() < 4f64;
+                    }
+                }
+                if let Some(Action::Spotlight(_, areas, _, _)) = data.actions.last() {
+                    if data.is_drawing {
+                        let area = areas.last().map(|(start_point, end_point, _)| Rect::from_points(*start_point, *end_point)).unwrap_or_default();
+                        too_small = area.width() < 4f64 || area.height() < 4f64;
+                    }
+                }
+                if too_small {
+                    data.actions.pop();
+                }
+                if let Some(Action::Callout(_, position, _, _, _, _, tail)) = data.actions.last_mut() {
+                    if data.is_drawing {
+                        *position = e.pos;
+                        if position.distance(*tail) < 10f64 {
+                            // a plain click places the box above the pointed location
+                            *position = *tail + (20f64, -3f64 * data.font_size);
+                        }
+                        data.writing_text = Some(data.actions.len() - 1);
+                        ctx.set_focus(self.text_box.id());
+                    }
                 }
                 if let Some(Action::Crop(prev_image, start_point, end_point)) = data.actions.last_mut() {
-                    *end_point = e.pos;
-                    let mut x = start_point.x;
-                    let mut y = start_point.y;
-                    let mut width = end_point.x - x;
-                    let mut height = end_point.y - y ;
+                    // the crop may have been dragged in any direction, or from its center
+                    let area = Rect::from_points(*start_point, *end_point);
+                    let mut x = area.x0;
+                    let mut y = area.y0;
+                    let mut width = area.width();
+                    let mut height = area.height();
 
                     x = ((x * prev_image.width() as f64) / ctx.size().width).floor();
                     y = ((y * prev_image.height() as f64) / ctx.size().height).floor();
@@ -245,35 +1597,46 @@ impl Widget<AppState> for DrawingWidget {
         }
     }
 
-    fn lifecycle(&mut self, _ctx: &mut druid::LifeCycleCtx, _event: &druid::LifeCycle, _data: &AppState, _env: &Env) {}
+    fn lifecycle(&mut self, ctx: &mut druid::LifeCycleCtx, event: &druid::LifeCycle, data: &AppState, env: &Env) {
+        self.text_box.lifecycle(ctx, event, &editing_text(data), &text_box_env(env, data.color));
+    }
 
-    fn update(&mut self, ctx: &mut druid::UpdateCtx, _old_data: &AppState, data: &AppState, _env: &Env) {
+    fn update(&mut self, ctx: &mut druid::UpdateCtx, _old_data: &AppState, data: &AppState, env: &Env) {
+        if let Some(Action::Text(_, _, _, color, font_size, style) | Action::Callout(_, _, _, color, font_size, style, _)) = data.writing_text.and_then(|index| data.actions.get(index)) {
+            self.text_box.widget_mut().set_text_color(*color);
+            self.text_box.widget_mut().set_font(FontDescriptor::new(FontFamily::SYSTEM_UI).with_size(*font_size));
+            self.text_box.widget_mut().text_mut().borrow_mut().layout.set_text_alignment(style.alignment);
+        }
+        self.text_box.update(ctx, &editing_text(data), &text_box_env(env, data.color));
         ctx.request_layout();
         if data.repaint {
             ctx.request_paint();
         }
     }
 
-    fn layout(&mut self, ctx: &mut druid::LayoutCtx, _bc: &druid::BoxConstraints, data: &AppState, _env: &Env) -> druid::Size {
+    fn layout(&mut self, ctx: &mut druid::LayoutCtx, _bc: &druid::BoxConstraints, data: &AppState, env: &Env) -> druid::Size {
         let monitor = dScreen::get_monitors().first().unwrap().clone();
-        let monitor_width = monitor.virtual_work_rect().width();
-        let monitor_height = monitor.virtual_work_rect().height();
 
 
         let image_width = data.image.width() as f64;
         let image_height = data.image.height() as f64;
 
-        let window_width;
-        let window_height;
-        if image_width > image_height {
-            data.scale_factor.set(image_width / monitor_width + 0.5f64);
-            window_width = image_width / data.scale_factor.get();
-            window_height = (image_height * window_width) / image_width;
-        } else {
-            data.scale_factor.set(image_height / monitor_height + 0.5f64);
-            window_height = image_height / data.scale_factor.get();
-            window_width = (image_width * window_height) / image_height;
+        data.scale_factor.set(utils::canvas_scale(image_width, image_height, &monitor));
+        let window_width = image_width / data.scale_factor.get();
+        let window_height = image_height / data.scale_factor.get();
+
+        let mut origin = Point::ZERO;
+        let mut text_bc = BoxConstraints::new(Size::ZERO, Size::new(window_width, window_height));
+        if let Some(Action::Text(_, position, _, _, _, style) | Action::Callout(_, position, _, _, _, style, _)) = data.writing_text.and_then(|index| data.actions.get(index)) {
+            origin = *position;
+            let max = Size::new((window_width - origin.x).max(0f64), (window_height - origin.y).max(0f64));
+            text_bc = match style.width {
+                Some(width) => BoxConstraints::new(Size::new(width.min(max.width), 0f64), Size::new(width.min(max.width), max.height)),
+                None => BoxConstraints::new(Size::ZERO, max),
+            };
         }
+        self.text_box.layout(ctx, &text_bc, &editing_text(data), &text_box_env(env, data.color));
+        self.text_box.set_origin(ctx, origin);
 
         #[cfg(not(target_os="windows"))]
         ctx.window().set_size((window_width, window_height + data.title_bar_height));
@@ -283,274 +1646,197 @@ impl Widget<AppState> for DrawingWidget {
     }
 
     fn paint(&mut self, ctx: &mut druid::PaintCtx, data: &AppState, env: &Env) {
-        let width;
-        let height;
-
-        width = ctx.size().width;
-        height = ctx.size().height;
+        let width = ctx.size().width;
+        let height = ctx.size().height;
         data.center.set(Point::new(width / 2f64, height / 2f64));
 
 
-        ctx.with_save(|ctx| {
-            for a in &data.affine {
-                ctx.render_ctx.transform(*a);
-                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-            }
-            let image;
-            if data.extension.eq("png") || data.extension.eq("tiff") || data.extension.eq("bmp") {
-                image = ctx.render_ctx.make_image(data.image.width(), data.image.height(), data.image.raw_pixels(), ImageFormat::RgbaSeparate).unwrap();
-            } else {
-                image = ctx.render_ctx.make_image(data.image.width(), data.image.height(), data.image.raw_pixels(), ImageFormat::Rgb).unwrap();
-            }
-            ctx.render_ctx.draw_image(&image, Rect::new(0f64, 0f64, width, height), InterpolationMode::Bilinear);
-        });
-
-        for action in &data.actions {
-            match action {
-                Action::Highlighter(affine, action, color, stroke) => {
-                    if action.len() < 2 {
-                        ctx.with_save(|ctx| {
-                            for a in &data.affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            for a in affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            ctx.render_ctx.fill(Circle::new(*action.last().unwrap(), stroke * 2f64), &color.with_alpha(0.25));
-                        });
-                    }
-                    for pair in action.windows(2) {
-                        if let [start, end] = pair {
-                            ctx.with_save(|ctx| {
-                                for a in &data.affine {
-                                    ctx.render_ctx.transform(*a);
-                                    if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                    if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                                }
-                                for a in affine {
-                                    ctx.render_ctx.transform(*a);
-                                    if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                    if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                                }
-                                let line = Line::new(*start, *end);
-                                ctx.render_ctx.stroke(line, &color.with_alpha(0.25), stroke * 3f64)
-                            });
-                        }
-                    }
-                }
-                Action::Pen(affine, action, color, stroke) => {
-                    if action.len() < 2 {
-                        ctx.with_save(|ctx| {
-                            for a in &data.affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            for a in affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            ctx.render_ctx.fill(Circle::new(*action.last().unwrap(), stroke / 2f64), color);
-                        });
-                    }
-                    for pair in action.windows(2) {
-                        if let [start, end] = pair {
-                            ctx.with_save(|ctx| {
-                                for a in &data.affine {
-                                    ctx.render_ctx.transform(*a);
-                                    if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                    if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                                }
-                                for a in affine {
-                                    ctx.render_ctx.transform(*a);
-                                    if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                    if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                                }
-                                let line = Line::new(*start, *end);
-                                ctx.render_ctx.stroke(line, color, *stroke);
-                            });
-                        }
-                    }
-                }
-                Action::Rectangle(affine, start_point, end_point, color, fill, stroke) => {
-                    if *fill {
-                        ctx.with_save(|ctx| {
-                            for a in &data.affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            for a in affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            ctx.render_ctx.fill_even_odd(Rect::new(start_point.x, start_point.y, end_point.x, end_point.y), color);
-                        });
-                    } else {
-                        ctx.with_save(|ctx| {
-                            for a in &data.affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            for a in affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            ctx.render_ctx.stroke(Rect::new(start_point.x, start_point.y, end_point.x, end_point.y), color, *stroke);
-                        });
-                    }
-                }
-                Action::Circle(affine, center, radius, color, fill, stroke) => {
-                    if *fill {
-                        ctx.with_save(|ctx| {
-                            for a in &data.affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            for a in affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            ctx.render_ctx.fill_even_odd(Circle::new(*center, *radius), color);
-                        });
-                    } else {
-                        ctx.with_save(|ctx| {
-                            for a in &data.affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            for a in affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            ctx.render_ctx.stroke(Circle::new(*center, *radius), color, *stroke);
-                        });
-                    }
-                }
-                Action::Ellipse(affine, start_point, end_point, color, fill, stroke) => {
-                    if *fill {
-                        ctx.with_save(|ctx| {
-                            for a in &data.affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            for a in affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            ctx.render_ctx.fill_even_odd(Ellipse::from_rect(Rect::new(start_point.x, start_point.y, end_point.x, end_point.y)), color);
-                        });
-                    } else {
-                        ctx.with_save(|ctx| {
-                            for a in &data.affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            for a in affine {
-                                ctx.render_ctx.transform(*a);
-                                if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                                if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                            }
-                            ctx.render_ctx.stroke(Ellipse::from_rect(Rect::new(start_point.x, start_point.y, end_point.x, end_point.y)), color, *stroke);
-                        });
-                    }
-                }
-                Action::Arrow(affine, start_point, end_point, color, stroke) => {
-                    ctx.with_save(|ctx| {
-                        for a in &data.affine {
-                            ctx.render_ctx.transform(*a);
-                            if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                            if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                        }
-                        for a in affine {
-                            ctx.render_ctx.transform(*a);
-                            if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                            if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                        }
-                        // Draw the line
-                        let line = Line::new(*start_point, *end_point);
-                        let len = line.length();
-                        ctx.render_ctx.stroke(line, color, *stroke);
-                        // Calculate the arrowhead points
-                        let arrowhead_length = len / 10f64;
-                        let arrowhead_width = len * 5f64 / 100f64;
-                        let (left_point, right_point) = calculate_arrowhead(*start_point, *end_point, arrowhead_length, arrowhead_width);
-                        // Draw the arrowhead
-                        let arrowhead = Line::new(left_point, *end_point);
-                        ctx.render_ctx.stroke(arrowhead, color, *stroke);
-                        let arrowhead = Line::new(right_point, *end_point);
-                        ctx.render_ctx.stroke(arrowhead, color, *stroke);
-                    });
-                }
-                Action::Text(affine, pos, text, color, font_size) => {
-                    ctx.with_save(|ctx| {
-                        for a in &data.affine {
-                            ctx.render_ctx.transform(*a);
-                            if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                            if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                        }
-                        for a in affine {
-                            ctx.render_ctx.transform(*a);
-                            if a == &Affine::FLIP_Y { ctx.render_ctx.transform(Affine::translate((0.0, -height))); }
-                            if a == &Affine::FLIP_X { ctx.render_ctx.transform(Affine::translate((-width, 0.0))); }
-                        }
-                        let mut layout = TextLayout::<String>::from_text(text.to_string());
-                        layout.set_font(FontDescriptor::new(FontFamily::SYSTEM_UI).with_size(*font_size));
-                        layout.set_text_color(*color);
-                        layout.rebuild_if_needed(ctx.text(), env);
-                        layout.draw(ctx, *pos);
-                    });
-                }
-                Action::Crop(_, start_point, end_point) => {
-                    if data.crop.get() {
-                        let background_color = Color::rgba(1.0, 1.0, 1.0, 0.05);
-                        ctx.fill(Rect::from_points(*start_point, *end_point), &background_color);
-
-                        // Set the border color
-                        let border_color = Color::GRAY;
+        paint_image(ctx.render_ctx, data, ctx.size());
+        paint_actions(ctx.render_ctx, data, ctx.size(), data.writing_text, env);
+        paint_watermark(ctx.render_ctx, data, ctx.size(), env);
 
-                        // Draw the border
-                        let border_width = 1.0;
-                        let border_rect = Rect::from_points(*start_point, *end_point).inset(-border_width / 2.0);
-                        let stroke_style = StrokeStyle::new().dash_pattern(&[2.0]);
-                        ctx.stroke_styled(border_rect, &border_color, border_width, &stroke_style);
-                    }
-                }
-            }
+        if data.writing_text.is_some() {
+            self.text_box.paint(ctx, &editing_text(data), &text_box_env(env, data.color));
         }
 
         if data.save.get() {
-            let x = ctx.window().get_position().x;
-            let y = ctx.window().get_position().y + data.title_bar_height;
-            let width = ctx.size().width;
-            let height = ctx.size().height;
-            #[cfg(not(target_os = "macos"))]
-            std::thread::sleep(std::time::Duration::from_millis(300));
-            let image = capture_image_area(Rect::new(x, y, width, height));
-            image.save(data.image_path.to_string()).unwrap();
             data.save.set(false);
+            let saved = render_image(data, ctx.size(), env).map_err(|error| error.to_string()).and_then(|mut image| {
+                if !(data.extension.eq("png") || data.extension.eq("tiff") || data.extension.eq("bmp")) {
+                    image = DynamicImage::ImageRgb8(image.to_rgb8());
+                }
+                image.save(&data.image_path).map_err(|error| error.to_string())
+            });
+            if let Err(error) = saved {
+                utils::dialog_image_not_saved(data.image_path.to_string(), error);
+            }
         }
     }
 }
 
+/// Renders the image with its annotations offscreen, at the resolution of the image rather than the size it is shown at.
+fn render_image(data: &AppState, size: Size, env: &Env) -> Result<DynamicImage, druid::piet::Error> {
+    let (width, height) = (data.image.width(), data.image.height());
+    let mut device = Device::new()?;
+    let mut target = device.bitmap_target(width, height, 1.0)?;
+    {
+        let mut rc = target.render_context();
+        rc.transform(Affine::scale_non_uniform(width as f64 / size.width, height as f64 / size.height));
+        paint_image(&mut rc, data, size);
+        // the text being written is saved as it is, the editor not being painted here
+        paint_actions(&mut rc, data, size, None, env);
+        paint_watermark(&mut rc, data, size, env);
+        rc.finish()?;
+    }
+    let mut pixels = target.to_image_buf(ImageFormat::RgbaPremul)?.raw_pixels().to_vec();
+    utils::unpremultiply(&mut pixels);
+    Ok(DynamicImage::ImageRgba8(image::RgbaImage::from_raw(width as u32, height as u32, pixels).unwrap()))
+}
+
 fn capture_image_area(rect: Rect) -> DynamicImage {
     let screens = Screen::all().unwrap();
     let screen = screens.iter().map(|screen| { (screen, num_traits::abs(rect.x0.floor() as i32 - screen.display_info.x)) }).min_by_key(|screen| { screen.1 }).unwrap().0;
-    return DynamicImage::ImageRgba8(screen.capture_area(num_traits::abs(rect.x0.floor() as i32 - screen.display_info.x), num_traits::abs(rect.y0.floor() as i32 - screen.display_info.y), rect.x1.ceil() as u32, rect.y1.ceil() as u32).unwrap());
+    DynamicImage::ImageRgba8(screen.capture_area(num_traits::abs(rect.x0.floor() as i32 - screen.display_info.x), num_traits::abs(rect.y0.floor() as i32 - screen.display_info.y), rect.x1.ceil() as u32, rect.y1.ceil() as u32).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(point: Point, expected: (f64, f64)) {
+        assert!(point.distance(expected.into()) < 1e-9, "{point:?} is not {expected:?}");
+    }
+
+    #[test]
+    fn snap_angle_keeps_the_length_along_the_nearest_eighth_of_a_turn() {
+        let start = Point::new(10.0, 10.0);
+        assert_near(snap_angle(start, Point::new(20.0, 11.0)), (10.0 + 101f64.sqrt(), 10.0));
+        assert_near(snap_angle(start, Point::new(11.0, -20.0)), (10.0, 10.0 - 901f64.sqrt()));
+        assert_near(snap_angle(start, Point::new(0.0, 0.0)), (0.0, 0.0));
+        assert_near(snap_angle(start, Point::new(13.0, 14.0)), (10.0 + 5.0 / 2f64.sqrt(), 10.0 + 5.0 / 2f64.sqrt()));
+        assert_near(snap_angle(start, start), (10.0, 10.0));
+    }
+
+    #[test]
+    fn constrain_squares_and_centers_boxes() {
+        let anchor = Point::new(10.0, 10.0);
+        assert_eq!(constrain(anchor, Point::new(16.0, 12.0), false, false), (anchor, Point::new(16.0, 12.0)));
+        assert_eq!(constrain(anchor, Point::new(16.0, 12.0), true, false), (anchor, Point::new(16.0, 16.0)));
+        assert_eq!(constrain(anchor, Point::new(7.0, 18.0), true, false), (anchor, Point::new(2.0, 18.0)));
+        assert_eq!(constrain(anchor, Point::new(16.0, 12.0), false, true), (Point::new(4.0, 8.0), Point::new(16.0, 12.0)));
+        assert_eq!(constrain(anchor, Point::new(16.0, 12.0), true, true), (Point::new(4.0, 4.0), Point::new(16.0, 16.0)));
+    }
+
+    #[test]
+    fn simplify_drops_points_close_to_the_line() {
+        let points = [(0.0, 0.0), (1.0, 0.1), (2.0, -0.1), (3.0, 0.0), (3.0, 5.0), (3.1, 6.0), (3.0, 8.0)].map(Point::from);
+        assert_eq!(simplify(&points, 0.5), [0, 3, 6]);
+        assert_eq!(simplify(&points, 0.09), [0, 1, 2, 3, 5, 6]);
+        assert_eq!(simplify(&points[..2], 10.0), [0, 1]);
+        assert!(simplify(&[], 1.0).is_empty());
+    }
+
+    #[test]
+    fn smooth_path_goes_through_every_point() {
+        let points = [(0.0, 0.0), (10.0, 5.0), (20.0, 0.0), (30.0, 10.0)].map(Point::from);
+        let path = smooth_path(&points);
+        let ends: Vec<Point> = path.segments().map(|segment| segment.end()).collect();
+        assert_eq!(path.segments().next().map(|segment| segment.start()), Some(points[0]));
+        assert_eq!(ends, points[1..]);
+        // points on a line stay on it
+        let straight = smooth_path(&[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)].map(Point::from));
+        assert!(straight.segments().all(|segment| (0..=10).map(|i| segment.eval(i as f64 / 10.0)).all(|p| (p.x - p.y).abs() < 1e-9)));
+    }
+
+    #[test]
+    fn split_stroke_cuts_where_the_eraser_crosses() {
+        let points = [(0.0, 0.0), (100.0, 0.0)].map(Point::from);
+        let pieces = split_stroke(&points, &[], Point::new(50.0, 0.0), 10.0);
+        assert_eq!(pieces.len(), 2);
+        let (first, second) = (&pieces[0].0, &pieces[1].0);
+        assert_eq!(first[0], points[0]);
+        assert_eq!(second[second.len() - 1], points[1]);
+        assert!(first.iter().chain(second).all(|point| point.distance((50.0, 0.0).into()) > 10.0));
+        // straight pieces keep only their ends
+        assert_eq!((first.len(), second.len()), (2, 2));
+        assert!(pieces.iter().all(|(_, widths)| widths.is_empty()));
+    }
+
+    #[test]
+    fn split_stroke_keeps_the_widths_of_the_points_left() {
+        let points = [(0.0, 0.0), (40.0, 0.0), (40.0, 40.0)].map(Point::from);
+        let pieces = split_stroke(&points, &[2.0, 4.0, 6.0], Point::new(40.0, 40.0), 5.0);
+        assert_eq!(pieces.len(), 1);
+        let (kept, widths) = &pieces[0];
+        assert_eq!(kept.len(), widths.len());
+        assert_eq!((kept[0], widths[0]), (Point::ZERO, 2.0));
+        assert_eq!((kept[1], widths[1]), (Point::new(40.0, 0.0), 4.0));
+        // erasing the whole stroke leaves nothing
+        assert!(split_stroke(&points[..2], &[], Point::new(20.0, 0.0), 30.0).is_empty());
+    }
+
+    #[test]
+    fn erasing_leaves_pieces_of_strokes_and_other_spotlight_areas() {
+        let pen = Action::Pen(Vec::new(), vec![Point::ZERO, Point::new(100.0, 0.0)], Color::RED, 2.0, Default::default(), false, Vec::new());
+        let pos = Point::new(50.0, 0.0);
+        assert!(left_after_erasing(&pen, EraserMode::Object, pos, 10.0).is_empty());
+        let pieces = left_after_erasing(&pen, EraserMode::Stroke, pos, 10.0);
+        assert_eq!(pieces.len(), 2);
+        assert!(pieces.iter().all(|piece| matches!(piece, Action::Pen(_, _, color, stroke, _, false, _) if *color == Color::RED && *stroke == 2.0)));
+        let areas = vec![(Point::ZERO, Point::new(20.0, 20.0), false), (Point::new(40.0, 40.0), Point::new(60.0, 60.0), true)];
+        let spotlight = Action::Spotlight(Vec::new(), areas.clone(), 0.5, 0.0);
+        assert_eq!(left_after_erasing(&spotlight, EraserMode::Object, Point::new(10.0, 10.0), 4.0), [Action::Spotlight(Vec::new(), areas[1..].to_vec(), 0.5, 0.0)]);
+        let rectangle = Action::Rectangle(Vec::new(), Point::ZERO, Point::new(20.0, 20.0), Color::RED, None, 2.0, Default::default());
+        assert!(left_after_erasing(&rectangle, EraserMode::Stroke, Point::ZERO, 4.0).is_empty());
+    }
+
+    #[test]
+    fn spotlight_areas_stay_in_place_across_flips() {
+        let size = Size::new(100.0, 50.0);
+        let areas = [(Point::new(10.0, 10.0), Point::new(30.0, 20.0), false), (Point::new(50.0, 0.0), Point::new(60.0, 50.0), true)];
+        assert_eq!(moved_areas(&areas, &[Affine::FLIP_X], &[Affine::FLIP_X], size), areas);
+        let flipped = moved_areas(&areas, &[], &[Affine::FLIP_X], size);
+        assert_eq!(flipped, [(Point::new(90.0, 10.0), Point::new(70.0, 20.0), false), (Point::new(50.0, 0.0), Point::new(40.0, 50.0), true)]);
+        assert_eq!(moved_areas(&flipped, &[Affine::FLIP_X], &[Affine::FLIP_X, Affine::FLIP_Y, Affine::FLIP_X], size), [
+            (Point::new(10.0, 40.0), Point::new(30.0, 30.0), false),
+            (Point::new(50.0, 50.0), Point::new(60.0, 0.0), true),
+        ]);
+    }
+
+    fn stroke(points: Vec<Point>) -> Action {
+        Action::Pen(Vec::new(), points, Color::RED, 2.0, Default::default(), true, Vec::new())
+    }
+
+    #[test]
+    fn recognize_shape_finds_rectangles_and_ellipses() {
+        let mut corners = Vec::new();
+        for (start, end) in [((0.0, 0.0), (100.0, 0.0)), ((100.0, 0.0), (100.0, 60.0)), ((100.0, 60.0), (0.0, 60.0)), ((0.0, 60.0), (0.0, 2.0))] {
+            corners.extend((0..10).map(|i| Point::from(start).lerp(end.into(), i as f64 / 10.0)));
+        }
+        let expected = Action::Rectangle(Vec::new(), Point::ZERO, Point::new(100.0, 60.0), Color::RED, None, 2.0, Default::default());
+        assert_eq!(recognize_shape(&stroke(corners), ArrowStyle::default()), Some(expected));
+        let oval = (0..40).map(|i| i as f64 / 40.0 * std::f64::consts::TAU).map(|angle| Point::new(50.0 + 50.0 * angle.cos(), 30.0 + 30.0 * angle.sin())).collect();
+        assert!(matches!(recognize_shape(&stroke(oval), ArrowStyle::default()), Some(Action::Ellipse(..))));
+    }
+
+    #[test]
+    fn recognize_shape_finds_lines_and_arrows() {
+        let line: Vec<Point> = (0..=20).map(|i| Point::new(i as f64 * 5.0, i as f64 * 2.0)).collect();
+        let expected = Action::Line(Vec::new(), Point::ZERO, Point::new(100.0, 40.0), Color::RED, 2.0, Default::default());
+        assert_eq!(recognize_shape(&stroke(line), ArrowStyle::default()), Some(expected));
+        let mut arrow: Vec<Point> = (0..=20).map(|i| Point::new(i as f64 * 5.0, 0.0)).collect();
+        // the head is drawn back from the tip, without reaching it again
+        arrow.extend([(92.0, -8.0), (96.0, 0.0), (92.0, 6.0)].map(Point::from));
+        let style = ArrowStyle { curved: true, ..ArrowStyle::default() };
+        assert!(matches!(recognize_shape(&stroke(arrow), style), Some(Action::Arrow(_, start, end, _, _, _, ArrowStyle { curved: false, .. }, None)) if start == Point::ZERO && end == Point::new(100.0, 0.0)));
+    }
+
+    #[test]
+    fn recognize_shape_leaves_scribbles_alone() {
+        let wave = (0..=40).map(|i| Point::new(i as f64 * 2.5, 20.0 * (i as f64 / 10.0 * std::f64::consts::PI).sin())).collect();
+        assert_eq!(recognize_shape(&stroke(wave), ArrowStyle::default()), None);
+        assert_eq!(recognize_shape(&stroke(vec![Point::ZERO, Point::new(5.0, 5.0)]), ArrowStyle::default()), None);
+        let rectangle = Action::Rectangle(Vec::new(), Point::ZERO, Point::new(100.0, 60.0), Color::RED, None, 2.0, Default::default());
+        assert_eq!(recognize_shape(&rectangle, ArrowStyle::default()), None);
+    }
 }
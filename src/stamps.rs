@@ -0,0 +1,74 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use image::{DynamicImage, RgbaImage};
+use crate::utils::unpremultiply;
+
+/// Side of the square SVG stamps are rendered in, large enough for them to stay sharp when enlarged.
+const STAMP_PIXELS: u32 = 256;
+
+const BUILT_IN: [(&str, &str); 9] = [
+    ("Check Mark", include_str!("../stamps/check.svg")),
+    ("Cross", include_str!("../stamps/cross.svg")),
+    ("Warning", include_str!("../stamps/warning.svg")),
+    ("Information", include_str!("../stamps/info.svg")),
+    ("Question", include_str!("../stamps/question.svg")),
+    ("Star", include_str!("../stamps/star.svg")),
+    ("Heart", include_str!("../stamps/heart.svg")),
+    ("Smile", include_str!("../stamps/smile.svg")),
+    ("Frown", include_str!("../stamps/frown.svg")),
+];
+
+/// An image that can be stamped on the screenshot.
+#[derive(Debug, Clone)]
+pub struct Stamp {
+    pub name: String,
+    pub image: DynamicImage,
+}
+
+/// Where users put their own PNG and SVG stamps.
+pub fn stamps_dir() -> Option<PathBuf> {
+    dirs_next::config_dir().map(|dir| dir.join("screen-crab-tools").join("stamps"))
+}
+
+/// The built-in stamps, followed by those in the stamps directory, named after their files.
+pub fn load_stamps() -> Vec<Stamp> {
+    let mut stamps: Vec<Stamp> = BUILT_IN.iter()
+        .filter_map(|(name, svg)| Some(Stamp { name: name.to_string(), image: render_svg(svg.as_bytes())? }))
+        .collect();
+    let Some(entries) = stamps_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return stamps;
+    };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    paths.sort();
+    for path in paths {
+        if let (Some(image), Some(name)) = (load_image(&path), path.file_stem().and_then(|name| name.to_str())) {
+            stamps.push(Stamp { name: name.to_string(), image });
+        }
+    }
+    stamps
+}
+
+/// Reads a PNG or SVG file as an image with transparency, as stamps and watermarks are.
+pub fn load_image(path: &Path) -> Option<DynamicImage> {
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase();
+    match extension.as_str() {
+        "svg" => fs::read(path).ok().and_then(|data| render_svg(&data)),
+        "png" => image::open(path).ok().map(|image| DynamicImage::ImageRgba8(image.to_rgba8())),
+        _ => None,
+    }
+}
+
+fn render_svg(data: &[u8]) -> Option<DynamicImage> {
+    render_svg_to(data, &usvg::Options::default(), usvg::FitTo::Size(STAMP_PIXELS, STAMP_PIXELS))
+}
+
+/// Renders an SVG document at the size `fit` gives it, as an image with straight alpha.
+pub fn render_svg_to(data: &[u8], options: &usvg::Options, fit: usvg::FitTo) -> Option<DynamicImage> {
+    let tree = usvg::Tree::from_data(data, &options.to_ref()).ok()?;
+    let size = fit.fit_to(tree.size.to_screen_size())?;
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())?;
+    resvg::render(&tree, fit, tiny_skia::Transform::identity(), pixmap.as_mut())?;
+    let mut pixels = pixmap.take();
+    unpremultiply(&mut pixels);
+    RgbaImage::from_raw(size.width(), size.height(), pixels).map(DynamicImage::ImageRgba8)
+}
@@ -1,9 +1,16 @@
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::path::Path;
-use druid::{Affine, Color, ImageBuf, Monitor, Point};
+use std::sync::Arc;
+use druid::{Affine, Color, ImageBuf, Insets, Monitor, Point, Rect, Size, TextAlignment, Vec2};
 use druid::{Data, Lens};
 use clap::Parser;
-use image::DynamicImage;
+use druid::piet::{ImageFormat, LineCap, LineJoin, StrokeStyle};
+use image::{DynamicImage, RgbImage, RgbaImage};
+use image::{Rgba, imageops};
+use image::imageops::FilterType;
+use crate::stamps::{load_stamps, Stamp};
+use crate::watermark::Watermark;
+use crate::mockups::{self, DeviceFrame, Mockup};
 
 /// Annotation Tools
 #[derive(Parser, Debug)]
@@ -12,50 +19,526 @@ pub struct Args {
     /// Name of the person to greet
     #[arg(short, long)]
     pub path: String,
+    /// Crop the uniform borders of the image when opening it
+    #[arg(long)]
+    pub auto_trim: bool,
+    /// How much the channels of a trimmed border may differ from its first pixel
+    #[arg(long, default_value_t = 8)]
+    pub trim_tolerance: u8,
+    /// Text, or PNG or SVG file, to watermark the image with, which cannot be turned off
+    #[arg(long)]
+    pub watermark: Option<String>,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Default)]
 pub enum Selection {
+    #[default]
     Pen,
     Highlighter,
     Rectangle,
+    RoundedRectangle,
     Circle,
     Ellipse,
+    Triangle,
+    Diamond,
+    Star,
+    Polygon,
     Arrow,
+    Line,
+    Polyline,
     Text,
+    Callout,
+    Step,
+    Magnifier,
+    Spotlight,
+    Stamp,
+    Eraser,
     Crop,
 }
+#[derive(PartialEq, Debug, Clone)]
+pub struct TextStyle {
+    pub background: Option<Color>,
+    pub outline: Option<Color>,
+    pub padding: f64,
+    pub radius: f64,
+    pub width: Option<f64>, // Wrap width, the text grows freely when not set
+    pub alignment: TextAlignment,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            background: None,
+            outline: None,
+            padding: 4.0,
+            radius: 0.0,
+            width: None,
+            alignment: TextAlignment::Start,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum DashStyle {
+    Solid,
+    Dashed,
+    Dotted,
+    DashDot,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct LineStyle {
+    pub dash: DashStyle,
+    pub cap: LineCap,
+    pub join: LineJoin,
+}
+
+impl Default for LineStyle {
+    fn default() -> Self {
+        LineStyle {
+            dash: DashStyle::Solid,
+            cap: LineCap::Round,
+            join: LineJoin::Round,
+        }
+    }
+}
+
+impl LineStyle {
+    /// The stroke style for a line of the given width, dashes growing with it.
+    pub fn stroke_style(&self, width: f64) -> StrokeStyle {
+        let mut style = StrokeStyle::new().line_cap(self.cap).line_join(self.join);
+        match self.dash {
+            DashStyle::Solid => {}
+            DashStyle::Dashed => style.set_dash_pattern(vec![4.0 * width, 2.0 * width]),
+            DashStyle::Dotted => style.set_dash_pattern(vec![width, 2.0 * width]),
+            DashStyle::DashDot => style.set_dash_pattern(vec![4.0 * width, 2.0 * width, width, 2.0 * width]),
+        }
+        style
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct MagnifierStyle {
+    pub round: bool,
+    pub connector: bool, // Whether a line joins the inset to the area it enlarges
+    pub zoom: f64,
+}
+
+impl Default for MagnifierStyle {
+    fn default() -> Self {
+        MagnifierStyle {
+            round: false,
+            connector: true,
+            zoom: 2.0,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum EraserMode {
+    Object, // Removes every annotation it touches
+    Stroke, // Only removes the touched parts of pen and highlighter strokes
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum HighlighterTip {
+    Round,
+    Chisel, // A flat tip held at 45°, thin along its edge and wide across it
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ArrowHead {
+    Open,
+    Filled,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ArrowTail {
+    None,
+    Head, // Makes a double-headed arrow
+    Dot,
+    Bar,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct ArrowStyle {
+    pub head: ArrowHead,
+    pub tail: ArrowTail,
+    pub curved: bool,
+}
 
-impl Default for Selection {
+impl Default for ArrowStyle {
     fn default() -> Self {
-        return Self::Pen
+        ArrowStyle {
+            head: ArrowHead::Open,
+            tail: ArrowTail::None,
+            curved: false,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum StepStyle {
+    Circle,
+    Square,
+    Letters,
+}
+
+impl StepStyle {
+    /// The label of the step at `index`, counting from zero.
+    pub fn label(&self, index: usize) -> String {
+        match self {
+            StepStyle::Letters => {
+                let mut label = String::new();
+                let mut n = index + 1;
+                while n > 0 {
+                    label.insert(0, (b'A' + ((n - 1) % 26) as u8) as char);
+                    n = (n - 1) / 26;
+                }
+                label
+            }
+            _ => (index + 1).to_string(),
+        }
     }
 }
+
+/// Shapes drawn as a polygon fitted in the dragged rectangle
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Shape {
+    Triangle,
+    Diamond,
+    Star,
+    Regular(usize), // Number of sides
+}
+
+impl Shape {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shape::Triangle => "Triangle",
+            Shape::Diamond => "Diamond",
+            Shape::Star => "Star",
+            Shape::Regular(_) => "Polygon",
+        }
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub enum Action {
-    Pen(Vec<Affine>, Vec<Point>, Color, f64),
-    Highlighter(Vec<Affine>, Vec<Point>, Color, f64),
-    Rectangle(Vec<Affine>, Point, Point, Color, bool, f64), // Stores rectangle points and color
-    Circle(Vec<Affine>, Point, f64, Color, bool, f64), // Stores circle points and color
-    Ellipse(Vec<Affine>, Point, Point, Color, bool, f64), // Stores ellipse points and color
-    Arrow(Vec<Affine>, Point, Point, Color, f64), // Stores arrow points and color
-    Text(Vec<Affine>, Point, String, Color, f64),  // Stores position, text, and color
+    Pen(Vec<Affine>, Vec<Point>, Color, f64, LineStyle, bool, Vec<f64>), // Stores points, color, whether it is smoothed and the width at each point when it varies with speed
+    Highlighter(Vec<Affine>, Vec<Point>, Color, f64, HighlighterTip),
+    Rectangle(Vec<Affine>, Point, Point, Color, Option<Color>, f64, LineStyle), // Stores rectangle points, stroke and fill color
+    RoundedRectangle(Vec<Affine>, Point, Point, Color, Option<Color>, f64, LineStyle, f64), // Stores the same as rectangle, plus the corner radius
+    Circle(Vec<Affine>, Point, f64, Color, Option<Color>, f64, LineStyle), // Stores circle points, stroke and fill color
+    Ellipse(Vec<Affine>, Point, Point, Color, Option<Color>, f64, LineStyle), // Stores ellipse points, stroke and fill color
+    Polygon(Vec<Affine>, Point, Point, Color, Option<Color>, f64, LineStyle, Shape), // Stores the corners of the rectangle the shape fits in, stroke and fill color, and shape
+    Arrow(Vec<Affine>, Point, Point, Color, f64, LineStyle, ArrowStyle, Option<Point>), // Stores arrow points, color, style and the control point of curved arrows
+    Line(Vec<Affine>, Point, Point, Color, f64, LineStyle), // Stores line points and color
+    Polyline(Vec<Affine>, Vec<Point>, Color, bool, Option<Color>, f64, LineStyle), // Stores vertices, color, whether it is closed and the fill color
+    Text(Vec<Affine>, Point, String, Color, f64, TextStyle),  // Stores position, text, color, font size and style
+    Callout(Vec<Affine>, Point, String, Color, f64, TextStyle, Point), // Stores the same as text, plus the point the tail points to
+    Step(Vec<Affine>, Point, Color, f64, StepStyle), // Stores center, color, font size and style; the number follows the order of the steps
+    Magnify(Vec<Affine>, Point, Point, Option<Point>, Color, f64, MagnifierStyle), // Stores the corners of the enlarged area, the center of the inset once placed, border color and style
+    Spotlight(Vec<Affine>, Vec<(Point, Point, bool)>, f64, f64), // Stores the corners of the areas left bright and whether they are round, and how much the rest is dimmed and blurred
+    Stamp(Vec<Affine>, Point, f64, DynamicImage), // Stores center, width and image
+    Erase(Vec<(usize, Vec<Action>)>, bool), // Stores what is left of the annotations it touched, by index, and whether it replaced a stroke with the shape recognized in it
     Crop(DynamicImage, Point, Point),
+    Trim(DynamicImage, Vec2), // Stores the image on the other side of the trim, and how far annotations move when it is swapped back in
 }
 
 impl Action {
     pub fn new(selection: &Selection) -> Self {
         match selection {
-            Selection::Pen => Self::Pen(Vec::<Affine>::new(), Vec::new(), Color::RED, 2.0),
-            Selection::Highlighter => Self::Highlighter(Vec::<Affine>::new(),Vec::new(), Color::RED, 2.0),
-            Selection::Rectangle => Self::Rectangle(Vec::<Affine>::new(),Point::ZERO, Point::ZERO, Color::RED, false, 2.0),
-            Selection::Circle => Self::Circle(Vec::<Affine>::new(),Point::ZERO, 0.0, Color::RED, false,2.0),
-            Selection::Ellipse => Self::Ellipse(Vec::<Affine>::new(),Point::ZERO, Point::ZERO, Color::RED, false, 2.0),
-            Selection::Arrow => Self::Arrow(Vec::<Affine>::new(),Point::ZERO, Point::ZERO, Color::RED, 2.0),
-            Selection::Text => Self::Text(Vec::<Affine>::new(),Point::ZERO, String::from("") ,Color::RED, 24f64),
+            Selection::Pen => Self::Pen(Vec::<Affine>::new(), Vec::new(), Color::RED, 2.0, LineStyle::default(), true, Vec::new()),
+            Selection::Highlighter => Self::Highlighter(Vec::<Affine>::new(),Vec::new(), Color::RED, 2.0, HighlighterTip::Round),
+            Selection::Rectangle => Self::Rectangle(Vec::<Affine>::new(),Point::ZERO, Point::ZERO, Color::RED, None, 2.0, LineStyle::default()),
+            Selection::Circle => Self::Circle(Vec::<Affine>::new(),Point::ZERO, 0.0, Color::RED, None,2.0, LineStyle::default()),
+            Selection::RoundedRectangle => Self::RoundedRectangle(Vec::<Affine>::new(),Point::ZERO, Point::ZERO, Color::RED, None, 2.0, LineStyle::default(), 8.0),
+            Selection::Ellipse => Self::Ellipse(Vec::<Affine>::new(),Point::ZERO, Point::ZERO, Color::RED, None, 2.0, LineStyle::default()),
+            Selection::Triangle => Self::Polygon(Vec::<Affine>::new(),Point::ZERO, Point::ZERO, Color::RED, None, 2.0, LineStyle::default(), Shape::Triangle),
+            Selection::Diamond => Self::Polygon(Vec::<Affine>::new(),Point::ZERO, Point::ZERO, Color::RED, None, 2.0, LineStyle::default(), Shape::Diamond),
+            Selection::Star => Self::Polygon(Vec::<Affine>::new(),Point::ZERO, Point::ZERO, Color::RED, None, 2.0, LineStyle::default(), Shape::Star),
+            Selection::Polygon => Self::Polygon(Vec::<Affine>::new(),Point::ZERO, Point::ZERO, Color::RED, None, 2.0, LineStyle::default(), Shape::Regular(6)),
+            Selection::Arrow => Self::Arrow(Vec::<Affine>::new(),Point::ZERO, Point::ZERO, Color::RED, 2.0, LineStyle::default(), ArrowStyle::default(), None),
+            Selection::Line => Self::Line(Vec::<Affine>::new(),Point::ZERO, Point::ZERO, Color::RED, 2.0, LineStyle::default()),
+            Selection::Polyline => Self::Polyline(Vec::<Affine>::new(), Vec::new(), Color::RED, false, None, 2.0, LineStyle::default()),
+            Selection::Text => Self::Text(Vec::<Affine>::new(),Point::ZERO, String::from("") ,Color::RED, 24f64, TextStyle::default()),
+            Selection::Callout => Self::Callout(Vec::<Affine>::new(),Point::ZERO, String::from("") ,Color::RED, 24f64, TextStyle::default(), Point::ZERO),
+            Selection::Step => Self::Step(Vec::<Affine>::new(), Point::ZERO, Color::RED, 24f64, StepStyle::Circle),
+            Selection::Magnifier => Self::Magnify(Vec::<Affine>::new(), Point::ZERO, Point::ZERO, None, Color::RED, 2.0, MagnifierStyle::default()),
+            Selection::Spotlight => Self::Spotlight(Vec::<Affine>::new(), Vec::new(), 0.5, 0.0),
+            Selection::Stamp => Self::Stamp(Vec::<Affine>::new(), Point::ZERO, 64.0, DynamicImage::default()),
+            Selection::Eraser => Self::Erase(Vec::new(), false),
             Selection::Crop => Self::Crop(DynamicImage::default(), Point::ZERO, Point::ZERO),
         }
     }
+
+    /// The transformations the annotation was drawn under, if it is one.
+    pub fn affine(&self) -> Option<&Vec<Affine>> {
+        match self {
+            Self::Pen(affine, ..) | Self::Highlighter(affine, ..) | Self::Rectangle(affine, ..) | Self::RoundedRectangle(affine, ..)
+            | Self::Circle(affine, ..) | Self::Ellipse(affine, ..) | Self::Polygon(affine, ..) | Self::Arrow(affine, ..)
+            | Self::Line(affine, ..) | Self::Polyline(affine, ..) | Self::Text(affine, ..) | Self::Callout(affine, ..)
+            | Self::Step(affine, ..) | Self::Magnify(affine, ..)
+            | Self::Spotlight(affine, ..) | Self::Stamp(affine, ..) => Some(affine),
+            Self::Erase(..) | Self::Crop(..) | Self::Trim(..) => None,
+        }
+    }
+
+    /// Moves the points of the annotation with `affine`, scaling its strokes and text by the mean scale of `affine`.
+    pub fn transform(&mut self, affine: Affine) {
+        let s = affine.determinant().abs().sqrt();
+        let map = |point: &mut Point| *point = affine * *point;
+        match self {
+            Self::Pen(_, points, _, stroke, _, _, widths) => {
+                points.iter_mut().for_each(map);
+                widths.iter_mut().for_each(|width| *width *= s);
+                *stroke *= s;
+            }
+            Self::Highlighter(_, points, _, stroke, _) | Self::Polyline(_, points, _, _, _, stroke, _) => {
+                points.iter_mut().for_each(map);
+                *stroke *= s;
+            }
+            Self::Rectangle(_, start_point, end_point, _, _, stroke, _)
+            | Self::Ellipse(_, start_point, end_point, _, _, stroke, _)
+            | Self::Polygon(_, start_point, end_point, _, _, stroke, _, _)
+            | Self::Line(_, start_point, end_point, _, stroke, _) => {
+                map(start_point);
+                map(end_point);
+                *stroke *= s;
+            }
+            Self::RoundedRectangle(_, start_point, end_point, _, _, stroke, _, radius) => {
+                map(start_point);
+                map(end_point);
+                *stroke *= s;
+                *radius *= s;
+            }
+            Self::Circle(_, center, radius, _, _, stroke, _) => {
+                map(center);
+                *radius *= s;
+                *stroke *= s;
+            }
+            Self::Arrow(_, start_point, end_point, _, stroke, _, _, control) => {
+                map(start_point);
+                map(end_point);
+                if let Some(control) = control {
+                    map(control);
+                }
+                *stroke *= s;
+            }
+            Self::Text(_, position, _, _, font_size, style) | Self::Callout(_, position, _, _, font_size, style, _) => {
+                map(position);
+                *font_size *= s;
+                style.padding *= s;
+                style.radius *= s;
+                if let Some(width) = &mut style.width {
+                    *width *= s;
+                }
+            }
+            Self::Step(_, center, _, font_size, _) => {
+                map(center);
+                *font_size *= s;
+            }
+            Self::Magnify(_, start_point, end_point, inset, _, stroke, _) => {
+                map(start_point);
+                map(end_point);
+                if let Some(inset) = inset {
+                    map(inset);
+                }
+                *stroke *= s;
+            }
+            Self::Spotlight(_, areas, _, _) => {
+                for (start_point, end_point, _) in areas {
+                    map(start_point);
+                    map(end_point);
+                }
+            }
+            Self::Stamp(_, center, width, _) => {
+                map(center);
+                *width *= s;
+            }
+            Self::Erase(changes, _) => {
+                for (_, left) in changes {
+                    left.iter_mut().for_each(|action| action.transform(affine));
+                }
+            }
+            Self::Crop(_, start_point, end_point) => {
+                map(start_point);
+                map(end_point);
+            }
+            Self::Trim(..) => {}
+        }
+        if let Self::Callout(_, _, _, _, _, _, tail) = self {
+            map(tail);
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Data)]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Lanczos3,
+}
+
+/// The settings of the Image Size dialog. The size is kept in pixels, whatever the unit shown.
+#[derive(Debug, Clone, Data, Lens)]
+pub struct ImageSize {
+    pub original_width: f64,
+    pub original_height: f64,
+    pub width: f64,
+    pub height: f64,
+    pub percent: bool,
+    pub locked: bool, // Whether changing one side changes the other, keeping the aspect ratio
+    pub filter: ResizeFilter,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Data)]
+pub enum CanvasColor {
+    White,
+    Black,
+    Gray,
+    Drawing, // The drawing color
+    Transparent,
+}
+
+impl CanvasColor {
+    pub fn color(&self, drawing: Color) -> Color {
+        match self {
+            CanvasColor::White => Color::WHITE,
+            CanvasColor::Black => Color::BLACK,
+            CanvasColor::Gray => Color::grey8(128),
+            CanvasColor::Drawing => drawing,
+            CanvasColor::Transparent => Color::TRANSPARENT,
+        }
+    }
+}
+
+/// The settings of the Canvas Size dialog, in pixels on each side of the image as it is shown.
+#[derive(Debug, Clone, Data, Lens)]
+pub struct Margins {
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+    pub left: f64,
+    pub background: CanvasColor,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Data)]
+pub enum FrameBackground {
+    Color, // The drawing color
+    Sunset,
+    Ocean,
+    Lavender,
+    Graphite,
+    Image,
+}
+
+impl FrameBackground {
+    /// The colors a gradient background goes from and to.
+    pub fn gradient(&self) -> ([u8; 3], [u8; 3]) {
+        match self {
+            FrameBackground::Sunset => ([0xff, 0x7e, 0x5f], [0xfe, 0xb4, 0x7b]),
+            FrameBackground::Ocean => ([0x21, 0x93, 0xb0], [0x6d, 0xd5, 0xed]),
+            FrameBackground::Lavender => ([0x83, 0x4d, 0x9b], [0xd0, 0x4e, 0xd6]),
+            _ => ([0x3a, 0x3a, 0x3c], [0x1c, 0x1c, 0x1e]),
+        }
+    }
+}
+
+/// The settings of the Frame dialog, in pixels.
+#[derive(Debug, Clone, Data, Lens)]
+pub struct Frame {
+    pub padding: f64,
+    pub radius: f64,
+    pub shadow: f64, // Blur radius of the shadow, none when 0
+    pub background: FrameBackground,
+    pub image_path: String, // Background image, when the background is one
+}
+
+/// The settings of the Border dialog, in pixels.
+#[derive(Debug, Clone, Data, Lens)]
+pub struct Border {
+    pub width: f64,
+    pub radius: f64, // Rounding of the corners of the image, the border follows it
+    pub color: CanvasColor,
+}
+
+/// Changes to the colors of the image, made when it is shown and saved, the image itself staying as it was.
+#[derive(PartialEq, Debug, Clone, Data, Lens)]
+pub struct Adjustments {
+    pub brightness: f64, // From -100 to 100
+    pub contrast: f64, // From -100 to 100
+    pub gamma: f64, // From 0.2 to 5, lightening the midtones above 1
+    pub saturation: f64, // From -100 to 100
+    pub sharpen: f64, // From 0 to 100
+    pub grayscale: bool,
+    pub invert: bool,
+}
+
+impl Default for Adjustments {
+    fn default() -> Self {
+        Adjustments { brightness: 0.0, contrast: 0.0, gamma: 1.0, saturation: 0.0, sharpen: 0.0, grayscale: false, invert: false }
+    }
+}
+
+impl Adjustments {
+    /// A copy of `image` with the adjustments made, in the same color type.
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        // brightness, contrast and gamma change each channel alike, so they are looked up
+        let levels: Vec<f64> = (0..=255).map(|value| {
+            let value = value as f64 / 255f64 + self.brightness / 200f64;
+            let value = ((value - 0.5) * (1f64 + self.contrast / 100f64) + 0.5).clamp(0f64, 1f64);
+            value.powf(1f64 / self.gamma.max(0.01))
+        }).collect();
+        let saturation = 1f64 + self.saturation / 100f64;
+        let mut pixels = image.to_rgba8();
+        for pixel in pixels.pixels_mut() {
+            let [r, g, b] = [0, 1, 2].map(|channel| levels[pixel[channel] as usize]);
+            let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+            for (channel, value) in [r, g, b].into_iter().enumerate() {
+                let mut value = if self.grayscale { luma } else { luma + (value - luma) * saturation };
+                if self.invert {
+                    value = 1f64 - value;
+                }
+                pixel[channel] = (value.clamp(0f64, 1f64) * 255f64).round() as u8;
+            }
+        }
+        if self.sharpen > 0f64 {
+            // unsharp masking: the details the blur takes away are added again
+            let amount = self.sharpen / 50f64;
+            let blurred = imageops::blur(&pixels, 1.0);
+            for (pixel, blurred) in pixels.pixels_mut().zip(blurred.pixels()) {
+                for channel in 0..3 {
+                    let value = pixel[channel] as f64 + (pixel[channel] as f64 - blurred[channel] as f64) * amount;
+                    pixel[channel] = value.round().clamp(0f64, 255f64) as u8;
+                }
+            }
+        }
+        if image.color().has_alpha() {
+            DynamicImage::ImageRgba8(pixels)
+        } else {
+            DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(pixels).to_rgb8())
+        }
+    }
+}
+
+impl ImageSize {
+    /// Sets the width in pixels, and the height with it when the aspect ratio is locked.
+    pub fn set_width(&mut self, width: f64) {
+        self.width = width.round().max(1f64);
+        if self.locked {
+            self.height = (self.width * self.original_height / self.original_width).round().max(1f64);
+        }
+    }
+
+    /// Sets the height in pixels, and the width with it when the aspect ratio is locked.
+    pub fn set_height(&mut self, height: f64) {
+        self.height = height.round().max(1f64);
+        if self.locked {
+            self.width = (self.height * self.original_width / self.original_height).round().max(1f64);
+        }
+    }
 }
 
 #[derive(Debug, Clone, Data, Lens)]
@@ -75,6 +558,8 @@ pub struct AppState {
     #[data(same_fn = "PartialEq::eq")]
     pub redo_actions: Vec<Action>,
     pub is_drawing: bool,
+    pub is_drawing_polyline: bool,
+    pub close_polyline: bool,
     pub image_path: String,
     #[data(same_fn = "PartialEq::eq")]
     pub monitor: Monitor,
@@ -83,8 +568,42 @@ pub struct AppState {
     pub is_picking_color: bool,
     pub custom_color: bool,
     pub fill_color: bool,
+    pub fill: Option<Color>, // Fill color, the stroke color when not set
+    pub fill_opacity: f64,
+    pub corner_radius: f64,
+    pub polygon_sides: usize,
     pub stroke: f64,
-    pub is_writing_text: bool,
+    pub pen_smoothing: bool,
+    #[data(same_fn = "PartialEq::eq")]
+    pub highlighter_tip: HighlighterTip,
+    pub straight_highlight: bool,
+    #[data(same_fn = "PartialEq::eq")]
+    pub eraser_mode: EraserMode,
+    #[data(same_fn = "PartialEq::eq")]
+    pub magnifier_style: MagnifierStyle,
+    pub spotlight_round: bool,
+    pub spotlight_dim: f64,
+    pub spotlight_blur: f64,
+    pub stamps: Arc<Vec<Stamp>>,
+    pub stamp: usize, // Index of the stamp placed by the stamp tool
+    pub stamp_size: f64,
+    pub image_size: ImageSize,
+    pub margins: Margins,
+    pub border: Border,
+    pub frame: Frame,
+    pub trim_tolerance: u8, // How much the channels of a border may differ from its first pixel
+    pub adjustments: Adjustments,
+    pub watermark: Watermark,
+    pub mockup: Mockup,
+    #[data(ignore)]
+    adjusted: RefCell<Option<(Adjustments, ImageBuf, ImageBuf)>>, // The last adjusted image, with what it was made from
+    #[data(ignore)]
+    blurred: RefCell<Option<(f64, ImageBuf, ImageBuf)>>, // The last blurred copy of the shown image, with what it was made from
+    pub pen_velocity: bool,
+    pub smart_pen: bool,
+    #[data(same_fn = "PartialEq::eq")]
+    pub line_style: LineStyle,
+    pub writing_text: Option<usize>,
     #[data(same_fn = "PartialEq::eq")]
     pub save: Cell<bool>,
     #[data(same_fn = "PartialEq::eq")]
@@ -93,6 +612,12 @@ pub struct AppState {
     #[data(same_fn = "PartialEq::eq")]
     pub crop: Cell<bool>,
     pub font_size: f64,
+    #[data(same_fn = "PartialEq::eq")]
+    pub text_style: TextStyle,
+    #[data(same_fn = "PartialEq::eq")]
+    pub step_style: StepStyle,
+    #[data(same_fn = "PartialEq::eq")]
+    pub arrow_style: ArrowStyle,
     pub title_bar_height: f64,
 }
 
@@ -110,6 +635,8 @@ impl AppState {
             actions: Vec::<Action>::new(),
             redo_actions: Vec::<Action>::new(),
             is_drawing: false,
+            is_drawing_polyline: false,
+            close_polyline: false,
             image_path,
             monitor,
             color,
@@ -117,13 +644,426 @@ impl AppState {
             is_picking_color: false,
             custom_color: false,
             fill_color: false,
+            fill: None,
+            fill_opacity: 1.0,
+            corner_radius: 8.0,
+            polygon_sides: 6,
             stroke: 2.0,
-            is_writing_text: false,
+            pen_smoothing: true,
+            highlighter_tip: HighlighterTip::Round,
+            straight_highlight: false,
+            eraser_mode: EraserMode::Object,
+            magnifier_style: MagnifierStyle::default(),
+            spotlight_round: false,
+            spotlight_dim: 0.5,
+            spotlight_blur: 0.0,
+            stamps: Arc::new(load_stamps()),
+            stamp: 0,
+            stamp_size: 64.0,
+            image_size: ImageSize {
+                original_width: 0.0,
+                original_height: 0.0,
+                width: 0.0,
+                height: 0.0,
+                percent: false,
+                locked: true,
+                filter: ResizeFilter::Lanczos3,
+            },
+            margins: Margins {
+                top: 0.0,
+                right: 0.0,
+                bottom: 0.0,
+                left: 0.0,
+                background: CanvasColor::White,
+            },
+            border: Border {
+                width: 8.0,
+                radius: 0.0,
+                color: CanvasColor::Black,
+            },
+            frame: Frame {
+                padding: 64.0,
+                radius: 12.0,
+                shadow: 24.0,
+                background: FrameBackground::Ocean,
+                image_path: String::new(),
+            },
+            trim_tolerance: 8,
+            adjustments: Adjustments::default(),
+            watermark: Watermark::load_defaults(),
+            mockup: Mockup {
+                frame: DeviceFrame::Browser,
+                title: "https://example.com".to_string(),
+            },
+            adjusted: RefCell::new(None),
+            blurred: RefCell::new(None),
+            pen_velocity: false,
+            smart_pen: false,
+            line_style: LineStyle::default(),
+            writing_text: None,
             update: Cell::new(false),
             zoom: 1f64,
             save: Cell::new(false),
             crop: Cell::new(false),
             font_size: 24f64,
+            text_style: TextStyle::default(),
+            step_style: StepStyle::Circle,
+            arrow_style: ArrowStyle::default(),
+        }
+    }
+
+    /// The annotations left once erasing is applied, in painting order, along with their index in `actions`.
+    /// Erasing never removes actions, so undoing it is just undoing the erase action.
+    pub fn visible_actions(&self) -> Vec<(usize, &Action)> {
+        let mut slots: Vec<Vec<&Action>> = self.actions.iter().map(|action| vec![action]).collect();
+        for (index, action) in self.actions.iter().enumerate() {
+            if let Action::Erase(changes, _) = action {
+                for (changed, left) in changes {
+                    slots[*changed] = left.iter().collect();
+                }
+                slots[index].clear();
+            }
+        }
+        slots.into_iter().enumerate().flat_map(|(index, slot)| slot.into_iter().map(move |action| (index, action))).collect()
+    }
+
+    /// Records a new annotation or change. The undone actions cannot be redone after it,
+    /// as erasures refer to the actions before them by index.
+    pub fn push_action(&mut self, action: Action) {
+        self.redo_actions.clear();
+        self.actions.push(action);
+    }
+
+    /// Takes back the last action, keeping it to be redone.
+    pub fn undo(&mut self) {
+        if let Some(mut action) = self.actions.pop() {
+            self.swap_trim(&mut action);
+            self.redo_actions.push(action);
+        }
+    }
+
+    /// Does again the last action undone.
+    pub fn redo(&mut self) {
+        if let Some(mut action) = self.redo_actions.pop() {
+            self.swap_trim(&mut action);
+            self.actions.push(action);
+        }
+    }
+
+    /// The spotlight currently shown, with its index: each spotlight holds the areas of the previous ones,
+    /// so only the latest is painted.
+    pub fn spotlight(&self) -> Option<(usize, &Action)> {
+        self.visible_actions().into_iter().rev().find(|(_, action)| matches!(action, Action::Spotlight(..)))
+    }
+
+    /// The image as it is shown and saved, with its adjustments made.
+    pub fn shown_image(&self) -> ImageBuf {
+        if self.adjustments == Adjustments::default() {
+            return self.image.clone();
+        }
+        let mut adjusted = self.adjusted.borrow_mut();
+        if let Some((adjustments, source, image)) = adjusted.as_ref() {
+            if *adjustments == self.adjustments && source.same(&self.image) {
+                return image.clone();
+            }
+        }
+        let image = ImageBuf::from_dynamic_image(self.adjustments.apply(&self.dynamic_image()));
+        *adjusted = Some((self.adjustments.clone(), self.image.clone(), image.clone()));
+        image
+    }
+
+    /// The shown image blurred by `radius`, as spotlights put it around their areas.
+    pub fn blurred_image(&self, radius: f64) -> ImageBuf {
+        let shown = self.shown_image();
+        let mut blurred = self.blurred.borrow_mut();
+        if let Some((blur, source, image)) = blurred.as_ref() {
+            if *blur == radius && source.same(&shown) {
+                return image.clone();
+            }
+        }
+        let image = ImageBuf::from_dynamic_image(blur_image(&to_dynamic_image(&shown), radius));
+        *blurred = Some((radius, shown, image.clone()));
+        image
+    }
+
+    /// The image being annotated, as the image crate sees it.
+    pub fn dynamic_image(&self) -> DynamicImage {
+        to_dynamic_image(&self.image)
+    }
+
+    /// Resizes the image to the size chosen in the Image Size dialog, scaling the annotations with it
+    /// so that they stay where they were on the image.
+    pub fn resize_image(&mut self) {
+        let (width, height) = (self.image_size.width as u32, self.image_size.height as u32);
+        if width == 0 || height == 0 || (width as usize, height as usize) == (self.image.width(), self.image.height()) {
+            return;
+        }
+        let filter = match self.image_size.filter {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        };
+        self.scale_image(width, height, filter);
+    }
+
+    /// Scales the image to the given size, and the annotations with it.
+    fn scale_image(&mut self, width: u32, height: u32, filter: FilterType) {
+        self.forget_trims();
+        let before = self.canvas_size();
+        let image = self.dynamic_image().resize_exact(width, height, filter);
+        self.image = if self.image.format() == ImageFormat::Rgb { ImageBuf::from_dynamic_image_without_alpha(image) } else { ImageBuf::from_dynamic_image(image) };
+        // annotations live in the coordinates of the canvas, which does not grow as much as the image
+        let after = self.canvas_size();
+        let (sx, sy) = (after.width / before.width, after.height / before.height);
+        self.actions.iter_mut().chain(self.redo_actions.iter_mut()).for_each(|action| action.transform(Affine::scale_non_uniform(sx, sy)));
+        self.repaint = true;
+    }
+
+    /// Adds the margins chosen in the Canvas Size dialog around the image.
+    pub fn expand_canvas(&mut self) {
+        let size = &self.margins;
+        let margins = Insets::new(size.left, size.top, size.right, size.bottom);
+        let (r, g, b, a) = size.background.color(self.color).as_rgba8();
+        self.extend_image(margins, 0f64, |width, height, _| RgbaImage::from_pixel(width, height, Rgba([r, g, b, a])));
+    }
+
+    /// Surrounds the image with the border chosen in the Border dialog, rounding its corners.
+    pub fn add_border(&mut self) {
+        let width = self.border.width.max(0f64).round();
+        let radius = self.border.radius.max(0f64);
+        let (r, g, b, a) = self.border.color.color(self.color).as_rgba8();
+        self.extend_image(Insets::uniform(width), radius, |canvas_width, canvas_height, _| {
+            let mut canvas = RgbaImage::from_pixel(canvas_width, canvas_height, Rgba([r, g, b, a]));
+            // the border follows the rounded corners of the image
+            round_corners(&mut canvas, if radius > 0f64 { radius + width } else { 0f64 });
+            canvas
+        });
+    }
+
+    /// Whether the image is shown flipped horizontally, and vertically.
+    fn flipped(&self) -> (bool, bool) {
+        let flipped = |flip: Affine| self.affine.iter().filter(|a| **a == flip).count() % 2 == 1;
+        (flipped(Affine::FLIP_X), flipped(Affine::FLIP_Y))
+    }
+
+    /// Scales the image into the device frame chosen in the Device Frame dialog, on a transparent background.
+    pub fn add_device_frame(&mut self) {
+        let (width, height) = (self.image.width() as u32, self.image.height() as u32);
+        let layout = mockups::geometry(self.mockup.frame, width, height);
+        let Some(mut frame) = mockups::render(self.mockup.frame, &self.mockup.title, width, height) else {
+            dialog_frame_not_rendered();
+            return;
+        };
+        self.scale_image(layout.image.width() as u32, layout.image.height() as u32, FilterType::Lanczos3);
+        // the frame is drawn as the image is shown, so it is flipped back with it
+        let (flipped_x, flipped_y) = self.flipped();
+        if flipped_x {
+            imageops::flip_horizontal_in_place(&mut frame);
+        }
+        if flipped_y {
+            imageops::flip_vertical_in_place(&mut frame);
+        }
+        let margins = Insets::new(layout.image.x0, layout.image.y0, layout.width as f64 - layout.image.x1, layout.height as f64 - layout.image.y1);
+        self.extend_image(margins, layout.radius, |_, _, _| frame);
+    }
+
+    /// Puts the image on the background, padding and shadow chosen in the Frame dialog, rounding its corners.
+    pub fn add_frame(&mut self) {
+        let frame = self.frame.clone();
+        let background = match frame.background {
+            FrameBackground::Image => match image::open(&frame.image_path) {
+                Ok(background) => Some(background),
+                Err(error) => {
+                    dialog_background_not_read(frame.image_path, error.to_string());
+                    return;
+                }
+            },
+            _ => None,
+        };
+        let color = self.color;
+        self.extend_image(Insets::uniform(frame.padding.max(0f64)), frame.radius.max(0f64), |width, height, image| {
+            let mut canvas = match (frame.background, background) {
+                (FrameBackground::Image, Some(background)) => background.resize_to_fill(width, height, FilterType::Triangle).to_rgba8(),
+                (FrameBackground::Color, _) => {
+                    let (r, g, b, a) = color.as_rgba8();
+                    RgbaImage::from_pixel(width, height, Rgba([r, g, b, a]))
+                }
+                (background, _) => {
+                    // diagonal gradients, from the top left corner to the bottom right one
+                    let (from, to) = background.gradient();
+                    RgbaImage::from_fn(width, height, |x, y| {
+                        let t = (x as f64 / width as f64 + y as f64 / height as f64) / 2f64;
+                        let mix = |i: usize| (from[i] as f64 + (to[i] as f64 - from[i] as f64) * t).round() as u8;
+                        Rgba([mix(0), mix(1), mix(2), 255])
+                    })
+                }
+            };
+            if frame.shadow > 0f64 {
+                draw_shadow(&mut canvas, image, frame.radius, frame.shadow);
+            }
+            canvas
+        });
+    }
+
+    /// Puts the image, its corners rounded by `radius`, on a larger one made by `backdrop` from its size
+    /// and where the image goes. Margins are in pixels, on the sides of the image as it is shown,
+    /// and annotations move with the image.
+    fn extend_image(&mut self, margins: Insets, radius: f64, backdrop: impl FnOnce(u32, u32, Rect) -> RgbaImage) {
+        let margins = Insets::new(margins.x0.max(0f64).round(), margins.y0.max(0f64).round(), margins.x1.max(0f64).round(), margins.y1.max(0f64).round());
+        if margins.size() == Size::ZERO && radius == 0f64 {
+            return;
+        }
+        self.forget_trims();
+        // the image is flipped when shown, so are its margins
+        let (flipped_x, flipped_y) = self.flipped();
+        let (left, right) = if flipped_x { (margins.x1, margins.x0) } else { (margins.x0, margins.x1) };
+        let (top, bottom) = if flipped_y { (margins.y1, margins.y0) } else { (margins.y0, margins.y1) };
+
+        let mut image = self.dynamic_image().to_rgba8();
+        round_corners(&mut image, radius);
+        let (width, height) = (image.width() + (left + right) as u32, image.height() + (top + bottom) as u32);
+        let mut canvas = backdrop(width, height, Rect::from_origin_size((left, top), (image.width() as f64, image.height() as f64)));
+        imageops::overlay(&mut canvas, &image, left as i64, top as i64);
+
+        let opaque = canvas.pixels().all(|pixel| pixel[3] == 255);
+        let canvas = if opaque && self.image.format() == ImageFormat::Rgb {
+            DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(canvas).to_rgb8())
+        } else {
+            DynamicImage::ImageRgba8(canvas)
+        };
+        self.replace_image(canvas, Vec2::new(margins.x0, margins.y0));
+    }
+
+    /// Crops the uniform borders of the image, within the trim tolerance, as a step that can be undone.
+    pub fn auto_trim(&mut self) {
+        let image = self.dynamic_image();
+        let Some((x, y, width, height)) = trim_area(&image, self.trim_tolerance) else {
+            return;
+        };
+        // annotations move by the borders cut on the top and the left of the image as it is shown
+        let (flipped_x, flipped_y) = self.flipped();
+        let left = if flipped_x { image.width() - x - width } else { x };
+        let top = if flipped_y { image.height() - y - height } else { y };
+        self.replace_image(image.crop_imm(x, y, width, height), Vec2::new(-(left as f64), -(top as f64)));
+        self.push_action(Action::Trim(image, Vec2::new(left as f64, top as f64)));
+    }
+
+    /// Undoes or redoes a trim, swapping the image it holds with the current one.
+    fn swap_trim(&mut self, trim: &mut Action) {
+        if let Action::Trim(image, offset) = trim {
+            let current = self.dynamic_image();
+            self.replace_image(std::mem::replace(image, current), *offset);
+            *offset = -*offset;
+        }
+    }
+
+    /// Drops the trims that could be undone or redone, as the images they hold would bring back
+    /// the image as it was before another change of it.
+    fn forget_trims(&mut self) {
+        let done = self.actions.iter().filter(|action| !matches!(action, Action::Trim(..))).count();
+        // undone actions are redone last first, and erasures refer to the actions they touched by index
+        let all: Vec<Action> = self.actions.drain(..).chain(self.redo_actions.drain(..).rev()).collect();
+        let mut kept = 0;
+        let moved: Vec<usize> = all.iter().map(|action| {
+            let index = kept;
+            if !matches!(action, Action::Trim(..)) {
+                kept += 1;
+            }
+            index
+        }).collect();
+        let mut all: Vec<Action> = all.into_iter().filter(|action| !matches!(action, Action::Trim(..))).collect();
+        for action in &mut all {
+            if let Action::Erase(changes, _) = action {
+                changes.iter_mut().for_each(|(index, _)| *index = moved[*index]);
+            }
+        }
+        self.writing_text = self.writing_text.map(|index| moved[index]);
+        self.redo_actions = all.split_off(done);
+        self.redo_actions.reverse();
+        self.actions = all;
+    }
+
+    /// Shows `image` instead of the current one, moving the annotations by `offset` pixels of the new image,
+    /// on the sides of the image as it is shown, so that they stay where they were on the image.
+    fn replace_image(&mut self, image: DynamicImage, offset: Vec2) {
+        let before = self.canvas_size();
+        let before_scale = self.image.width() as f64 / before.width;
+        self.image = ImageBuf::from_dynamic_image(image);
+        let after = self.canvas_size();
+        let after_scale = self.image.width() as f64 / after.width;
+
+        // where a point of the old canvas is shown on the new one
+        let shown = Affine::translate(offset / after_scale) * Affine::scale(before_scale / after_scale);
+        let global = self.affine.clone();
+        let remap = |action: &mut Action| {
+            if let Some(affine) = action.affine() {
+                let old = transform(&global, before) * transform(affine, before);
+                let new = transform(&global, after) * transform(affine, after);
+                action.transform(new.inverse() * shown * old);
+            }
+        };
+        for action in self.actions.iter_mut().chain(self.redo_actions.iter_mut()) {
+            if let Action::Erase(changes, _) = action {
+                changes.iter_mut().flat_map(|(_, left)| left.iter_mut()).for_each(remap);
+            } else {
+                remap(action);
+            }
+        }
+        self.repaint = true;
+    }
+
+    /// The size the image is shown at.
+    pub fn canvas_size(&self) -> Size {
+        let (width, height) = (self.image.width() as f64, self.image.height() as f64);
+        let scale = canvas_scale(width, height, &self.monitor);
+        Size::new(width / scale, height / scale)
+    }
+
+    /// The fill color of new shapes, if they are filled.
+    pub fn shape_fill(&self) -> Option<Color> {
+        if !self.fill_color {
+            return None;
+        }
+        let (r, g, b, a) = self.fill.unwrap_or(self.color).as_rgba();
+        Some(Color::rgba(r, g, b, a * self.fill_opacity))
+    }
+
+    /// Sets the drawing color, restyling the text annotation being written too.
+    pub fn set_color(&mut self, color: Color) {
+        self.color = color;
+        if let Some(index) = self.writing_text {
+            if let Some(Action::Text(_, _, _, text_color, _, _) | Action::Callout(_, _, _, text_color, _, _, _)) = self.actions.get_mut(index) {
+                *text_color = color;
+            }
+        }
+    }
+
+    /// Sets the font size, restyling the text annotation being written too.
+    pub fn set_font_size(&mut self, font_size: f64) {
+        self.font_size = font_size;
+        if let Some(index) = self.writing_text {
+            if let Some(Action::Text(_, _, _, _, text_size, _) | Action::Callout(_, _, _, _, text_size, _, _)) = self.actions.get_mut(index) {
+                *text_size = font_size;
+            }
+        }
+    }
+
+    /// The style of the text annotation being written, or the one new text annotations get.
+    pub fn current_text_style(&self) -> &TextStyle {
+        if let Some(Action::Text(_, _, _, _, _, style) | Action::Callout(_, _, _, _, _, style, _)) = self.writing_text.and_then(|index| self.actions.get(index)) {
+            return style;
+        }
+        &self.text_style
+    }
+
+    /// Changes the text style, restyling the text annotation being written too.
+    pub fn update_text_style(&mut self, update: impl Fn(&mut TextStyle)) {
+        update(&mut self.text_style);
+        if let Some(index) = self.writing_text {
+            if let Some(Action::Text(_, _, _, _, _, style) | Action::Callout(_, _, _, _, _, style, _)) = self.actions.get_mut(index) {
+                update(style);
+            }
         }
     }
 }
@@ -148,4 +1088,333 @@ pub fn dialog_not_supported(path: String) {
         .show();
 }
 
+pub fn dialog_image_not_saved(path: String, error: String) {
+    tauri_dialog::DialogBuilder::new()
+        .title("Image Not Saved!")
+        .message(&format!("The image could not be saved to \"{}\": {}.", path, error))
+        .style(tauri_dialog::DialogStyle::Warning)
+        .buttons(tauri_dialog::DialogButtons::Ok)
+        .build()
+        .show();
+}
+
+pub fn dialog_background_not_read(path: String, error: String) {
+    tauri_dialog::DialogBuilder::new()
+        .title("Frame Not Added!")
+        .message(&format!("The background image \"{}\" could not be read: {}.\nPlease choose another image and try again.", path, error))
+        .style(tauri_dialog::DialogStyle::Warning)
+        .buttons(tauri_dialog::DialogButtons::Ok)
+        .build()
+        .show();
+}
+
+
+pub fn dialog_watermark_not_read(path: String) {
+    tauri_dialog::DialogBuilder::new()
+        .title("Watermark Not Supported!")
+        .message(&format!("The watermark \"{}\" could not be read. Please try again with a PNG or SVG image, or with the text to show.", path))
+        .style(tauri_dialog::DialogStyle::Error)
+        .buttons(tauri_dialog::DialogButtons::Quit)
+        .build()
+        .show();
+}
+
+/// Turns premultiplied RGBA pixels into straight ones, in place.
+pub fn unpremultiply(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u32;
+        if alpha > 0 && alpha < 255 {
+            for channel in &mut pixel[..3] {
+                *channel = (*channel as u32 * 255 / alpha).min(255) as u8;
+            }
+        }
+    }
+}
+
+/// How many pixels of an image of the given size make a point on the canvas, so that it fits the monitor.
+pub fn canvas_scale(image_width: f64, image_height: f64, monitor: &Monitor) -> f64 {
+    if image_width > image_height {
+        image_width / monitor.virtual_work_rect().width() + 0.5f64
+    } else {
+        image_height / monitor.virtual_work_rect().height() + 0.5f64
+    }
+}
+
+/// Composes a list of affine transformations the same way they are applied when painting.
+pub fn transform(affine: &[Affine], size: Size) -> Affine {
+    affine.iter().fold(Affine::IDENTITY, |transform, a| {
+        if a == &Affine::FLIP_Y { transform * *a * Affine::translate((0.0, -size.height)) }
+        else if a == &Affine::FLIP_X { transform * *a * Affine::translate((-size.width, 0.0)) }
+        else { transform * *a }
+    })
+}
 
+/// How much of the pixel at (`x`, `y`) is inside the rectangle from the origin to `width` × `height`
+/// with corners rounded by `radius`, from 0 to 1.
+fn rounded_coverage(x: u32, y: u32, width: u32, height: u32, radius: f64) -> f64 {
+    let (x, y) = (x as f64 + 0.5, y as f64 + 0.5);
+    let cx = x.clamp(radius, width as f64 - radius);
+    let cy = y.clamp(radius, height as f64 - radius);
+    let distance = ((x - cx).powi(2) + (y - cy).powi(2)).sqrt();
+    (radius - distance + 0.5).clamp(0f64, 1f64)
+}
+
+/// Makes the corners of `image` transparent outside of a rounding of the given radius.
+fn round_corners(image: &mut RgbaImage, radius: f64) {
+    if radius <= 0f64 {
+        return;
+    }
+    let (width, height) = image.dimensions();
+    let radius = radius.min(width as f64 / 2f64).min(height as f64 / 2f64);
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let coverage = rounded_coverage(x, y, width, height, radius);
+        if coverage < 1f64 {
+            pixel[3] = (pixel[3] as f64 * coverage).round() as u8;
+        }
+    }
+}
+
+/// `image` as the image crate sees it.
+fn to_dynamic_image(image: &ImageBuf) -> DynamicImage {
+    let (width, height) = (image.width() as u32, image.height() as u32);
+    let pixels = image.raw_pixels().to_vec();
+    match image.format() {
+        ImageFormat::Rgb => DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, pixels).unwrap()),
+        _ => DynamicImage::ImageRgba8(RgbaImage::from_raw(width, height, pixels).unwrap()),
+    }
+}
+
+/// A blurred copy of `image`. It is only ever seen stretched and blurry, so a quarter of the size is enough.
+fn blur_image(image: &DynamicImage, radius: f64) -> DynamicImage {
+    let (width, height) = ((image.width() / 4).max(1), (image.height() / 4).max(1));
+    DynamicImage::ImageRgba8(image.resize_exact(width, height, FilterType::Triangle).blur(radius as f32 / 4f32).to_rgba8())
+}
+
+/// Darkens `canvas` with the shadow of the area `under`, its corners rounded by `radius`,
+/// blurred by `blur` and falling a little below it.
+fn draw_shadow(canvas: &mut RgbaImage, under: Rect, radius: f64, blur: f64) {
+    // the shadow is blurry, so a quarter of the size is enough for the mask
+    let (width, height) = ((canvas.width() / 4).max(1), (canvas.height() / 4).max(1));
+    let area = Rect::new(under.x0 / 4f64, (under.y0 + blur / 2f64) / 4f64, under.x1 / 4f64, (under.y1 + blur / 2f64) / 4f64);
+    let (area_width, area_height) = (area.width().round() as u32, area.height().round() as u32);
+    let mut mask = image::GrayImage::new(width, height);
+    for (x, y, pixel) in mask.enumerate_pixels_mut() {
+        let (inside_x, inside_y) = (x as f64 - area.x0.round(), y as f64 - area.y0.round());
+        if inside_x >= 0f64 && inside_y >= 0f64 && (inside_x as u32) < area_width && (inside_y as u32) < area_height {
+            pixel[0] = (rounded_coverage(inside_x as u32, inside_y as u32, area_width, area_height, (radius / 4f64).min(area_width.min(area_height) as f64 / 2f64)) * 255f64) as u8;
+        }
+    }
+    let mask = imageops::resize(&imageops::blur(&mask, (blur / 4f64) as f32), canvas.width(), canvas.height(), FilterType::Triangle);
+    for (pixel, shade) in canvas.pixels_mut().zip(mask.pixels()) {
+        let alpha = shade[0] as f64 / 255f64 * 0.45;
+        for channel in &mut pixel.0[..3] {
+            *channel = (*channel as f64 * (1f64 - alpha)).round() as u8;
+        }
+        pixel[3] = pixel[3].max((alpha * 255f64) as u8);
+    }
+}
+
+/// The part of `image` left once its uniform borders are cropped, as x, y, width and height,
+/// if there is any border to crop. Each side is compared to its first pixel, channel by channel
+/// within `tolerance`, transparent pixels being all alike.
+pub fn trim_area(image: &DynamicImage, tolerance: u8) -> Option<(u32, u32, u32, u32)> {
+    let image = image.to_rgba8();
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let alike = |a: &Rgba<u8>, b: &Rgba<u8>| {
+        (a[3] == 0 && b[3] == 0) || a.0.iter().zip(b.0.iter()).all(|(a, b)| a.abs_diff(*b) <= tolerance)
+    };
+    let uniform_row = |y: u32, from: u32, to: u32, first: &Rgba<u8>| (from..to).all(|x| alike(image.get_pixel(x, y), first));
+    let uniform_column = |x: u32, from: u32, to: u32, first: &Rgba<u8>| (from..to).all(|y| alike(image.get_pixel(x, y), first));
+
+    let (mut top, mut bottom) = (0, height);
+    while top < bottom && uniform_row(top, 0, width, image.get_pixel(0, 0)) {
+        top += 1;
+    }
+    // the tolerance is not transitive, so the bottom border may reach the top one
+    while bottom > top && uniform_row(bottom - 1, 0, width, image.get_pixel(0, height - 1)) {
+        bottom -= 1;
+    }
+    if top == bottom {
+        // the whole image is borders
+        return None;
+    }
+    let (mut left, mut right) = (0, width);
+    while left < right && uniform_column(left, top, bottom, image.get_pixel(0, top)) {
+        left += 1;
+    }
+    while right > left && uniform_column(right - 1, top, bottom, image.get_pixel(width - 1, top)) {
+        right -= 1;
+    }
+    if left == right || (left, top, right, bottom) == (0, 0, width, height) {
+        return None;
+    }
+    Some((left, top, right - left, bottom - top))
+}
+
+pub fn dialog_not_saved(path: String, error: std::io::Error) {
+    tauri_dialog::DialogBuilder::new()
+        .title("Settings Not Saved!")
+        .message(&format!("The settings could not be saved to \"{}\": {}.", path, error))
+        .style(tauri_dialog::DialogStyle::Warning)
+        .buttons(tauri_dialog::DialogButtons::Ok)
+        .build()
+        .show();
+}
+
+pub fn dialog_frame_not_rendered() {
+    tauri_dialog::DialogBuilder::new()
+        .title("Frame Not Added!")
+        .message("The device frame could not be drawn around the image. Please try again with another frame or title.")
+        .style(tauri_dialog::DialogStyle::Warning)
+        .buttons(tauri_dialog::DialogButtons::Ok)
+        .build()
+        .show();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(width: u32, height: u32, pixel: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba(pixel))
+    }
+
+    fn state() -> AppState {
+        // there is no screen to ask for a monitor, and an all-zero one is a valid empty one
+        let monitor = unsafe { std::mem::zeroed::<Monitor>() };
+        AppState::new(DynamicImage::new_rgba8(8, 8), 0.0, "png".to_string(), 1.0, String::new(), monitor, Color::RED)
+    }
+
+    fn dot(x: f64) -> Action {
+        Action::Pen(Vec::new(), vec![Point::new(x, 0.0)], Color::RED, 2.0, LineStyle::default(), false, Vec::new())
+    }
+
+    #[test]
+    fn new_actions_drop_the_undone_ones() {
+        let mut data = state();
+        data.push_action(dot(1.0));
+        data.push_action(dot(2.0));
+        data.push_action(Action::Erase(vec![(1, Vec::new())], false));
+        data.undo();
+        data.undo();
+        assert_eq!(data.redo_actions.len(), 2);
+        // the erasure would now hide the new dot, which took the place of the erased one
+        data.push_action(dot(3.0));
+        data.redo();
+        data.redo();
+        let visible: Vec<&Action> = data.visible_actions().into_iter().map(|(_, action)| action).collect();
+        assert_eq!(visible, [&dot(1.0), &dot(3.0)]);
+        assert!(data.redo_actions.is_empty());
+    }
+
+    #[test]
+    fn undone_actions_are_redone_in_order() {
+        let mut data = state();
+        data.push_action(dot(1.0));
+        data.push_action(dot(2.0));
+        data.undo();
+        data.undo();
+        data.redo();
+        assert_eq!(data.actions, [dot(1.0)]);
+        data.redo();
+        assert_eq!(data.actions, [dot(1.0), dot(2.0)]);
+    }
+
+    #[test]
+    fn trim_area_crops_uniform_borders() {
+        let mut image = filled(10, 8, [255, 255, 255, 255]);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            if (2..7).contains(&x) && (1..5).contains(&y) {
+                *pixel = Rgba([200, 0, 0, 255]);
+            }
+        }
+        assert_eq!(trim_area(&DynamicImage::ImageRgba8(image), 8), Some((2, 1, 5, 4)));
+    }
+
+    #[test]
+    fn trim_area_leaves_uniform_images() {
+        assert_eq!(trim_area(&DynamicImage::ImageRgba8(filled(6, 4, [30, 60, 90, 255])), 0), None);
+        assert_eq!(trim_area(&DynamicImage::ImageRgba8(filled(1, 1, [30, 60, 90, 255])), 0), None);
+    }
+
+    #[test]
+    fn trim_area_leaves_two_tone_images() {
+        let mut image = filled(6, 4, [0, 0, 0, 255]);
+        image.enumerate_pixels_mut().filter(|(_, y, _)| *y >= 2).for_each(|(_, _, pixel)| *pixel = Rgba([255, 255, 255, 255]));
+        assert_eq!(trim_area(&DynamicImage::ImageRgba8(image), 8), None);
+    }
+
+    #[test]
+    fn trim_area_stops_where_borders_meet() {
+        // each row is alike the last one, but the middle one is not alike the first
+        let mut image = filled(3, 3, [0, 0, 0, 255]);
+        for (_, y, pixel) in image.enumerate_pixels_mut() {
+            let value = [0, 16, 8][y as usize];
+            *pixel = Rgba([value, value, value, 255]);
+        }
+        assert_eq!(trim_area(&DynamicImage::ImageRgba8(image), 8), None);
+    }
+
+    #[test]
+    fn trim_area_treats_transparent_pixels_alike() {
+        let mut image = filled(5, 5, [0, 0, 0, 0]);
+        image.put_pixel(4, 0, Rgba([255, 0, 0, 0]));
+        image.put_pixel(2, 2, Rgba([0, 0, 255, 255]));
+        assert_eq!(trim_area(&DynamicImage::ImageRgba8(image), 0), Some((2, 2, 1, 1)));
+        assert_eq!(trim_area(&DynamicImage::ImageRgba8(filled(5, 5, [0, 0, 0, 0])), 0), None);
+    }
+
+    #[test]
+    fn step_labels_count_from_one() {
+        assert_eq!(StepStyle::Circle.label(0), "1");
+        assert_eq!(StepStyle::Square.label(41), "42");
+    }
+
+    #[test]
+    fn step_letters_go_on_like_spreadsheet_columns() {
+        let labels: Vec<String> = [0, 1, 25, 26, 27, 51, 52, 701, 702].iter().map(|index| StepStyle::Letters.label(*index)).collect();
+        assert_eq!(labels, ["A", "B", "Z", "AA", "AB", "AZ", "BA", "ZZ", "AAA"]);
+    }
+
+    fn adjusted(adjustments: Adjustments, pixel: [u8; 4]) -> [u8; 4] {
+        let image = DynamicImage::ImageRgba8(filled(3, 3, pixel));
+        adjustments.apply(&image).to_rgba8().get_pixel(1, 1).0
+    }
+
+    #[test]
+    fn adjustments_leave_the_image_alone_by_default() {
+        assert_eq!(adjusted(Adjustments::default(), [12, 130, 250, 77]), [12, 130, 250, 77]);
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, image::Rgb([12, 130, 250])));
+        assert_eq!(Adjustments::default().apply(&image), image);
+    }
+
+    #[test]
+    fn adjustments_change_the_levels_of_every_channel() {
+        assert_eq!(adjusted(Adjustments { brightness: 100.0, ..Default::default() }, [128, 0, 200, 255]), [255, 128, 255, 255]);
+        assert_eq!(adjusted(Adjustments { contrast: -100.0, ..Default::default() }, [0, 90, 255, 255]), [128, 128, 128, 255]);
+        assert_eq!(adjusted(Adjustments { gamma: 2.0, ..Default::default() }, [0, 64, 255, 255]), [0, 128, 255, 255]);
+        assert_eq!(adjusted(Adjustments { invert: true, ..Default::default() }, [0, 100, 255, 40]), [255, 155, 0, 40]);
+    }
+
+    #[test]
+    fn adjustments_change_the_colorfulness() {
+        let [r, g, b, _] = adjusted(Adjustments { grayscale: true, ..Default::default() }, [200, 40, 90, 255]);
+        assert!(r == g && g == b);
+        assert_eq!(adjusted(Adjustments { saturation: -100.0, ..Default::default() }, [200, 40, 90, 255]), [r, g, b, 255]);
+        let [r, g, b, _] = adjusted(Adjustments { saturation: 50.0, ..Default::default() }, [150, 100, 100, 255]);
+        assert!(r > 150 && g < 100 && g == b);
+    }
+
+    #[test]
+    fn sharpening_brings_out_edges_only() {
+        let sharpen = Adjustments { sharpen: 100.0, ..Default::default() };
+        assert_eq!(adjusted(sharpen.clone(), [90, 90, 90, 255]), [90, 90, 90, 255]);
+        let mut image = filled(6, 1, [100, 100, 100, 255]);
+        (3..6).for_each(|x| image.put_pixel(x, 0, Rgba([150, 150, 150, 255])));
+        let sharpened = sharpen.apply(&DynamicImage::ImageRgba8(image)).to_rgba8();
+        assert!(sharpened.get_pixel(2, 0)[0] < 100 && sharpened.get_pixel(3, 0)[0] > 150);
+    }
+}
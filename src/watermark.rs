@@ -0,0 +1,209 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use druid::{Data, Lens};
+use image::DynamicImage;
+use crate::stamps::load_image;
+use crate::utils::{dialog_not_saved, CanvasColor};
+
+#[derive(PartialEq, Debug, Clone, Copy, Data)]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+    Tiled, // Repeated over the whole image
+}
+
+/// A text or an image put over the image and its annotations, when showing and saving them.
+#[derive(Debug, Clone, Data, Lens)]
+pub struct Watermark {
+    pub enabled: bool,
+    pub enforced: bool, // Set from the command line, so it can neither be changed nor saved as the default
+    pub use_image: bool,
+    pub text: String,
+    pub color: CanvasColor,
+    pub image_path: String,
+    pub image: Option<Arc<DynamicImage>>, // The image at `image_path`, when it could be read
+    pub position: WatermarkPosition,
+    pub opacity: f64, // From 0 to 1
+    pub rotation: f64, // In degrees, clockwise
+    pub size: f64, // Width of the watermark, in percents of the width of the image
+}
+
+impl Default for Watermark {
+    fn default() -> Self {
+        Watermark {
+            enabled: false,
+            enforced: false,
+            use_image: false,
+            text: "CONFIDENTIAL".to_string(),
+            color: CanvasColor::Gray,
+            image_path: String::new(),
+            image: None,
+            position: WatermarkPosition::Center,
+            opacity: 0.3,
+            rotation: -30.0,
+            size: 60.0,
+        }
+    }
+}
+
+/// Where the default watermark settings are kept.
+fn defaults_path() -> Option<PathBuf> {
+    dirs_next::config_dir().map(|dir| dir.join("screen-crab-tools").join("watermark.conf"))
+}
+
+impl Watermark {
+    /// The settings saved as defaults, or the built-in ones.
+    pub fn load_defaults() -> Self {
+        defaults_path().and_then(|path| fs::read_to_string(path).ok()).map_or_else(Watermark::default, |config| Watermark::parse_defaults(&config))
+    }
+
+    /// The settings in `config`, one `key = value` line each, the built-in ones standing for those missing.
+    fn parse_defaults(config: &str) -> Self {
+        let mut watermark = Watermark::default();
+        for line in config.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "enabled" => watermark.enabled = value == "true",
+                "text" => watermark.text = value.to_string(),
+                "image" => watermark.set_image_path(value.to_string()),
+                "use_image" => watermark.use_image = value == "true",
+                "color" => watermark.color = match value {
+                    "white" => CanvasColor::White,
+                    "black" => CanvasColor::Black,
+                    "drawing" => CanvasColor::Drawing,
+                    _ => CanvasColor::Gray,
+                },
+                "position" => watermark.position = match value {
+                    "top-left" => WatermarkPosition::TopLeft,
+                    "top-right" => WatermarkPosition::TopRight,
+                    "bottom-left" => WatermarkPosition::BottomLeft,
+                    "bottom-right" => WatermarkPosition::BottomRight,
+                    "tiled" => WatermarkPosition::Tiled,
+                    _ => WatermarkPosition::Center,
+                },
+                "opacity" => watermark.opacity = value.parse().unwrap_or(watermark.opacity),
+                "rotation" => watermark.rotation = value.parse().unwrap_or(watermark.rotation),
+                "size" => watermark.size = value.parse().unwrap_or(watermark.size),
+                _ => {}
+            }
+        }
+        watermark
+    }
+
+    /// Saves the settings as the defaults of the next sessions, telling the user when they cannot be.
+    pub fn save_defaults(&self) {
+        let Some(path) = defaults_path() else {
+            return;
+        };
+        if let Err(error) = self.write_defaults(&path) {
+            dialog_not_saved(path.to_string_lossy().to_string(), error);
+        }
+    }
+
+    fn write_defaults(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.defaults_config())
+    }
+
+    /// The settings as `parse_defaults` reads them.
+    fn defaults_config(&self) -> String {
+        let color = match self.color {
+            CanvasColor::White => "white",
+            CanvasColor::Black => "black",
+            CanvasColor::Drawing => "drawing",
+            _ => "gray",
+        };
+        let position = match self.position {
+            WatermarkPosition::TopLeft => "top-left",
+            WatermarkPosition::TopRight => "top-right",
+            WatermarkPosition::BottomLeft => "bottom-left",
+            WatermarkPosition::BottomRight => "bottom-right",
+            WatermarkPosition::Center => "center",
+            WatermarkPosition::Tiled => "tiled",
+        };
+        format!(
+            "enabled = {}\ntext = {}\nimage = {}\nuse_image = {}\ncolor = {}\nposition = {}\nopacity = {}\nrotation = {}\nsize = {}\n",
+            self.enabled, self.text, self.image_path, self.use_image, color, position, self.opacity, self.rotation, self.size,
+        )
+    }
+
+    /// Uses the PNG or SVG file at `path` as the watermark image, reading it again.
+    pub fn set_image_path(&mut self, path: String) {
+        self.image = load_image(Path::new(&path)).map(Arc::new);
+        self.image_path = path;
+    }
+
+    /// Turns on the watermark given on the command line: the image at `mark` when it names a file,
+    /// its text otherwise. Returns false when `mark` names an image that cannot be read.
+    pub fn enforce(&mut self, mark: &str) -> bool {
+        let path = Path::new(mark);
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase();
+        self.use_image = path.is_file() || extension == "png" || extension == "svg";
+        if self.use_image {
+            self.set_image_path(mark.to_string());
+            if self.image.is_none() {
+                return false;
+            }
+        } else {
+            self.text = mark.to_string();
+            self.image_path = String::new();
+            self.image = None;
+        }
+        self.enabled = true;
+        self.enforced = true;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_read_back_as_written() {
+        let watermark = Watermark {
+            enabled: true,
+            text: "Draft = not final".to_string(),
+            color: CanvasColor::Black,
+            position: WatermarkPosition::BottomRight,
+            opacity: 0.55,
+            rotation: 12.5,
+            size: 25.0,
+            ..Watermark::default()
+        };
+        let read = Watermark::parse_defaults(&watermark.defaults_config());
+        assert_eq!((read.enabled, read.use_image, &read.text, &read.image_path), (true, false, &watermark.text, &String::new()));
+        assert_eq!((read.color, read.position), (CanvasColor::Black, WatermarkPosition::BottomRight));
+        assert_eq!((read.opacity, read.rotation, read.size), (0.55, 12.5, 25.0));
+        for position in [WatermarkPosition::TopLeft, WatermarkPosition::TopRight, WatermarkPosition::BottomLeft, WatermarkPosition::Center, WatermarkPosition::Tiled] {
+            assert_eq!(Watermark::parse_defaults(&Watermark { position, ..Watermark::default() }.defaults_config()).position, position);
+        }
+    }
+
+    #[test]
+    fn defaults_fall_back_on_the_built_in_ones() {
+        let read = Watermark::parse_defaults("opacity = much\nsize=40\nunknown = 1\nno value here\ncolor = purple\n");
+        let default = Watermark::default();
+        assert_eq!((read.opacity, read.size, read.color), (default.opacity, 40.0, CanvasColor::Gray));
+        assert_eq!((read.enabled, read.text, read.position), (false, default.text, default.position));
+    }
+
+    #[test]
+    fn enforced_watermarks_fail_on_images_that_cannot_be_read() {
+        let mut watermark = Watermark::default();
+        assert!(watermark.enforce("Internal use only"));
+        assert!(watermark.enabled && watermark.enforced && !watermark.use_image);
+        assert_eq!(watermark.text, "Internal use only");
+        assert!(!Watermark::default().enforce("no such logo.png"));
+        assert!(!Watermark::default().enforce(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")));
+    }
+}